@@ -49,7 +49,7 @@ fn vento() -> Result<Page> {
     let content = Manual::new("vento")
         .about("a CLI inventory for your files")
         .author(Author::new("Lux Aliaga").email("lux@nixgoat.me"))
-        .description("List files and directories in the currently active inventory, the files in SLOT, the files in DIRECTORY or the files in DIRECTORY in SLOT.")
+        .description("List files and directories in the currently active inventory, the files in SLOT, the files in DIRECTORY or the files in DIRECTORY in SLOT. DIRECTORY can also be given as SLOT:DIRECTORY, such as active:docs.")
         .flag(
             Flag::new()
                 .short("-c")
@@ -98,6 +98,11 @@ fn vento() -> Result<Page> {
                 .long("--import-inv")
                 .help("Imports an inventory archive"),
         )
+        .flag(
+            Flag::new()
+                .long("--preview")
+                .help("Shows what importing an inventory archive would do, without importing it"),
+        )
         .flag(
             Flag::new()
                 .short("-G")
@@ -108,7 +113,94 @@ fn vento() -> Result<Page> {
             Flag::new()
                 .short("-i")
                 .long("--init")
-                .help("Initializes Vento with all its respective directories"),
+                .help("Initializes Vento with all its respective directories. If Vento was already initialized, the old directory is backed up next to it first"),
+        )
+        .option(
+            Opt::new("item")
+                .long("--edit")
+                .help("Edits the tags and note of an item in the slot picked with -s, using -t, --untag and -n"),
+        )
+        .option(
+            Opt::new("tag")
+                .short("-t")
+                .long("--tag")
+                .help("Adds a tag to the item being edited, or only finds items with it when used with --find. Can be given more than once"),
+        )
+        .option(
+            Opt::new("tag")
+                .long("--untag")
+                .help("Removes a tag from the item being edited. Can be given more than once"),
+        )
+        .option(
+            Opt::new("note")
+                .short("-n")
+                .long("--note")
+                .help("Sets the note of the item being edited, or clears it if empty"),
+        )
+        .option(
+            Opt::new("age")
+                .long("--ttl")
+                .help("Sets the item being edited to expire after AGE, such as 7d, or never if AGE is never"),
+        )
+        .option(
+            Opt::new("pattern")
+                .long("--find")
+                .help("Finds items across both slots recursively, optionally by a glob matched against their name, or their path if it has a slash in it"),
+        )
+        .flag(
+            Flag::new()
+                .long("--regex")
+                .help("Matches the pattern given to --find as a regex, rather than a glob"),
+        )
+        .option(
+            Opt::new("extension")
+                .long("--ext")
+                .help("Only finds items with this extension"),
+        )
+        .option(
+            Opt::new("size")
+                .long("--min-size")
+                .help("Only finds files at least this big, such as 10M"),
+        )
+        .option(
+            Opt::new("size")
+                .long("--max-size")
+                .help("Only finds files at most this big, such as 10M"),
+        )
+        .option(
+            Opt::new("age")
+                .long("--newer")
+                .help("Only finds items modified within this long, such as 30m, 12h, 7d or 2w"),
+        )
+        .option(
+            Opt::new("age")
+                .long("--older")
+                .help("Only finds items modified at least this long ago, such as 30m, 12h, 7d or 2w"),
+        )
+        .option(
+            Opt::new("destination")
+                .long("--drop")
+                .help("Drops the items found into DESTINATION, or the current directory if not provided. Matches inside other items are skipped"),
+        )
+        .flag(
+            Flag::new()
+                .long("--doctor")
+                .help("Checks the Vento directory for problems, such as missing slots, a switch that was interrupted halfway or history which doesn't match the slots' contents"),
+        )
+        .flag(
+            Flag::new()
+                .long("--fix")
+                .help("Fixes the problems found by --doctor"),
+        )
+        .flag(
+            Flag::new()
+                .long("--keep-history")
+                .help("Keeps the history when reinitializing Vento, resetting only the slots"),
+        )
+        .flag(
+            Flag::new()
+                .long("--keep-slots")
+                .help("Keeps every slot when reinitializing Vento, resetting only the history"),
         )
         .flag(
             Flag::new()
@@ -122,7 +214,129 @@ fn vento() -> Result<Page> {
                 .long("--slot")
                 .help("The slot to list"),
         )
+        .flag(
+            Flag::new()
+                .long("--tree")
+                .help("Lists the items inside of directories too"),
+        )
+        .option(
+            Opt::new("levels")
+                .long("--depth")
+                .help("Lists a tree only this many levels deep, implying --tree"),
+        )
+        .option(
+            Opt::new("order")
+                .long("--sort")
+                .help("Sorts the items listed by name (default), size, mtime or type. Sizes and modification times go from biggest and newest"),
+        )
+        .flag(
+            Flag::new()
+                .long("--reverse")
+                .help("Lists the items in reverse order"),
+        )
+        .flag(
+            Flag::new()
+                .long("--origin")
+                .help("Shows how long ago each item was taken, and the directory it was taken from"),
+        )
+        .option(
+            Opt::new("age")
+                .long("--stale-after")
+                .help("Flags items taken longer ago than this, such as 30d, overriding list.stale_after in the config file"),
+        )
+        .option(
+            Opt::new("archive")
+                .long("--list-archive")
+                .help("Lists the items inside an archive without importing it"),
+        )
+        .option(
+            Opt::new("item")
+                .long("--item")
+                .help("Imports a single item from an inventory archive"),
+        )
+        .option(
+            Opt::new("archive")
+                .long("--verify")
+                .help("Verifies an archive's contents against the manifest Vento embeds in it when exporting"),
+        )
+        .option(
+            Opt::new("format")
+                .short("-f")
+                .long("--format")
+                .help("The format to export archives into (tar.xz, tar.zst, tar.gz, tar or zip). Guessed from the archive's extension if not provided"),
+        )
+        .option(
+            Opt::new("level")
+                .long("--level")
+                .help("The compression level to export archives with"),
+        )
+        .option(
+            Opt::new("threads")
+                .long("--threads")
+                .help("The amount of threads to compress archives with"),
+        )
+        .flag(
+            Flag::new()
+                .long("--incremental")
+                .help("Exports only the files in the Vento directory which changed since its last export"),
+        )
+        .flag(
+            Flag::new()
+                .long("--differential")
+                .help("Exports only the files in the Vento directory which changed since its last full export"),
+        )
+        .option(
+            Opt::new("directory archive...")
+                .long("--restore")
+                .help("Restores a chain of Vento directory archives into an empty directory, starting with a full export followed by the incremental and differential ones based on it, in the order they were exported"),
+        )
+        .flag(
+            Flag::new()
+                .long("--snapshot")
+                .help("Saves a snapshot of the Vento directory into the snapshot directory, pruning older snapshots according to the retention policy"),
+        )
+        .option(
+            Opt::new("item")
+                .long("--discard")
+                .help("Throws an item in the slot given by --slot, or the active one, away into the trash set by trash.backend, recording it in the history so it can be undone"),
+        )
+        .flag(
+            Flag::new()
+                .long("--dedup")
+                .help("Stores every file in the Vento directory once by its contents, hardlinking copies to it, and removes stored contents nothing uses anymore"),
+        )
+        .flag(
+            Flag::new()
+                .long("--gc")
+                .help("Moves expired items into the trash, recording it in the history so it can be undone, permanently purges items which have been in the trash for longer than gc.grace and removes deduplicated contents nothing uses anymore"),
+        )
+        .flag(
+            Flag::new()
+                .long("--encrypt")
+                .help("Encrypts the exported archive with a passphrase"),
+        )
+        .option(
+            Opt::new("file")
+                .long("--key")
+                .help("Encrypts the exported archive for the age key file given, or decrypts an archive with it. Key files can hold either identities or public keys"),
+        )
+        .option(
+            Opt::new("policy")
+                .long("--conflict")
+                .help("Imports an inventory archive item by item, resolving name collisions by skipping, renaming, overwriting (backing up the old item) or keeping the newer item (skip, rename, overwrite, newer)"),
+        )
+        .option(
+            Opt::new("mode")
+                .long("--progress")
+                .help("Reports the progress of exports, imports and files copied across filesystems as a bar, as lines of JSON or not at all. MODE is auto, bar, json or quiet. Defaults to progress.mode in the config file"),
+        )
         .arg(Arg::new("[DIRECTORY]"))
+        .custom(
+            Section::new("archives")
+            .paragraph("Any ARCHIVE given to the export and import flags can be - to write it into the standard output or read it from the standard input, such as in vento -e active - | ssh host vento -g - active. Zip archives can't be written into the standard output.")
+            .paragraph("Archives can be encrypted with a passphrase using --encrypt, or for an age key file using --key. Encrypted archives are decrypted transparently when imported, listed or verified. The passphrase is asked for in the terminal, unless it's set through the VENTO_PASSPHRASE environment variable. Zip archives can't be encrypted.")
+            .paragraph("Every export of the Vento directory is recorded in .vento-snapshots inside of it, which is how --incremental and --differential tell what changed. Files are compared by their size and modification time, and files removed since are listed in the archive's manifest so --restore can remove them too.")
+        )
         .custom(
             Section::new("before starting")
             .paragraph("Vento will first need to initialize the respective directories before usage. Do this by running vento -i.")
@@ -144,7 +358,40 @@ fn take() -> Result<Page> {
             Opt::new("slot")
                 .short("-s")
                 .long("--slot")
-                .help("The slot to put the file in"),
+                .help("The slot to put the file in: active, inactive or templates. A directory inside the slot can be given as SLOT:DIRECTORY, such as active:docs, which is made if it doesn't exist"),
+        )
+        .option(
+            Opt::new("tag")
+                .short("-t")
+                .long("--tag")
+                .help("Tags the file. Can be given more than once"),
+        )
+        .option(
+            Opt::new("note")
+                .short("-n")
+                .long("--note")
+                .help("Attaches a note to the file"),
+        )
+        .option(
+            Opt::new("age")
+                .long("--ttl")
+                .help("Expires the file after AGE, such as 7d, so vento --gc moves it into the trash. Defaults to ttl.active or ttl.inactive in the config file"),
+        )
+        .flag(
+            Flag::new()
+                .long("--push")
+                .help("Pushes the file onto the active slot, so drop --pop gives it back before anything taken earlier"),
+        )
+        .flag(
+            Flag::new()
+                .short("-l")
+                .long("--link")
+                .help("Leaves a symlink where the file was, pointing to it inside the slot. Dropping the file back replaces the symlink with it. Links don't follow items into the other slot when switching, and can't be made into compressed slots nor outside of Unix systems"),
+        )
+        .option(
+            Opt::new("mode")
+                .long("--progress")
+                .help("Reports the progress of files copied across filesystems as a bar, as lines of JSON or not at all. MODE is auto, bar, json or quiet. Defaults to progress.mode in the config file"),
         )
         .arg(Arg::new("FILE"))
         .render();
@@ -159,14 +406,34 @@ fn drop() -> Result<Page> {
     let content = Manual::new("drop")
         .about("a file dropper for Vento")
         .author(Author::new("Lux Aliaga").email("lux@nixgoat.me"))
-        .description("Take FILE off the inventory and drop it in DESTINATION.")
+        .description("Take FILE off the inventory and drop it in DESTINATION. Without FILE, drops the item taken most recently into the current directory. FILE can be inside a directory in the slot, such as docs/notes.md, and can also be given as SLOT:FILE, such as active:docs/notes.md.")
         .option(
             Opt::new("slot")
                 .short("-s")
                 .long("--slot")
-                .help("The slot to take the file from"),
+                .help("The slot to take the file from. Items in the templates slot are copied out instead of moved, so they can be dropped again"),
         )
-        .arg(Arg::new("FILE"))
+        .option(
+            Opt::new("count")
+                .long("--pop")
+                .help("Drops the COUNT items taken into the slot most recently, last one first, according to the history. COUNT defaults to 1"),
+        )
+        .option(
+            Opt::new("directory")
+                .long("--into")
+                .help("The directory to drop popped items in. Defaults to the current directory"),
+        )
+        .flag(
+            Flag::new()
+                .long("--render")
+                .help("Replaces {{project}} with the name of the git repository or directory FILE is dropped in, {{date}} with today's date and {{author}} with user.name from git's config, in the names and contents of a template as it's copied out"),
+        )
+        .option(
+            Opt::new("mode")
+                .long("--progress")
+                .help("Reports the progress of files copied across filesystems as a bar, as lines of JSON or not at all. MODE is auto, bar, json or quiet. Defaults to progress.mode in the config file"),
+        )
+        .arg(Arg::new("[FILE]"))
         .arg(Arg::new("[DESTINATION]"))
         .render();
 
@@ -188,6 +455,29 @@ fn ventotoml() -> Result<Page> {
             .paragraph("display_colors = (true | false): Sets whether messages will be colored.")
             .paragraph("item.display_dir = (true | false): Sets whether item actions will show the paths involved in the operation.")
             .paragraph("history.display_dir = (true | false): Sets whether history actions will show the paths involved in the operation.")
+            .paragraph("archive.format = \"FORMAT\": Sets the format archives are exported into when it can't be guessed from their name (tar.xz, tar.zst, tar.gz, tar or zip). Defaults to tar.xz.")
+            .paragraph("archive.level = NUMBER: Sets the compression level archives are exported with. Defaults to 9 for tar.xz, 3 for tar.zst and 6 for tar.gz and zip.")
+            .paragraph("archive.threads = NUMBER: Sets the amount of threads archives are compressed with, for tar.xz and tar.zst. Defaults to 1.")
+            .paragraph("archive.encrypt = (true | false): Sets whether exported archives are always encrypted, using archive.key_file if set or a passphrase otherwise. Defaults to false.")
+            .paragraph("archive.key_file = \"PATH\": Sets the age key file used to encrypt and decrypt archives when --key isn't given.")
+            .paragraph("snapshot.directory = \"PATH\": Sets the directory snapshots are saved into. Defaults to vento/snapshots inside the user's data directory.")
+            .paragraph("snapshot.keep_last = NUMBER: Sets how many of the newest snapshots are kept. Defaults to 5.")
+            .paragraph("snapshot.keep_daily = NUMBER: Keeps the newest snapshot of each of the last NUMBER days. Defaults to 7.")
+            .paragraph("snapshot.keep_weekly = NUMBER: Keeps the newest snapshot of each of the last NUMBER weeks. Defaults to 4.")
+            .paragraph("snapshot.auto = (true | false): Sets whether a snapshot is saved automatically before reinitializing Vento or importing an archive. Defaults to false.")
+            .paragraph("init.backup = (\"move\" | \"archive\"): Sets how the old Vento directory is backed up when reinitializing. move renames it to a timestamped directory next to it, while archive exports it into a timestamped archive next to it, leaving the trash and older backups in place. Defaults to move.")
+            .paragraph("list.stale_after = \"AGE\": Flags listed items taken longer ago than AGE, such as \"30d\". Units are s, m, h, d and w.")
+            .paragraph("ttl.active = \"AGE\": Sets how long items taken into the active slot last before expiring, such as \"7d\". Items don't expire by default.")
+            .paragraph("ttl.inactive = \"AGE\": Sets how long items taken into the inactive slot last before expiring. Items don't expire by default.")
+            .paragraph("gc.grace = \"AGE\": Sets how long items stay in the trash before vento --gc purges them. Defaults to 30d.")
+            .paragraph("storage.dedup = (true | false): Sets whether files taken or imported are stored once by their contents inside the Vento directory, and hardlinked into the slots. Copies of a file only share its contents while they're inside Vento, and items taken with take --link always keep their own. Defaults to false.")
+            .paragraph("progress.mode = (\"auto\" | \"bar\" | \"json\" | \"quiet\"): Sets how the progress of taking, dropping, exporting and importing is reported into the standard error. auto shows a progress bar with the bytes and files done, the throughput and the time left when the standard error is a terminal. json writes a line of JSON for every update, for other programs to follow along. Defaults to auto.")
+            .paragraph("compress.active = (true | false): Sets whether files put into the active slot are stored compressed with zstd. Items are decompressed when they're dropped, and listings show how much space they take up on disk. Defaults to false.")
+            .paragraph("compress.inactive = (true | false): Sets whether files put into the inactive slot are stored compressed with zstd. Defaults to false.")
+            .paragraph("compress.level = LEVEL: Sets the zstd level files are compressed at, from 1 to 22. Defaults to 3.")
+            .paragraph("trash.backend = (\"xdg\" | \"internal\"): Sets where vento --discard throws items away into. xdg uses the user's trash, following the freedesktop.org Trash specification, while internal uses the trash inside the Vento directory which vento --gc purges. Defaults to xdg on Linux and BSD, and internal elsewhere.")
+            .paragraph("archive.max_size = \"SIZE\": Sets the maximum amount of data an archive can unpack to when imported, such as \"16G\". Defaults to 64G. 0 disables the limit.")
+            .paragraph("archive.max_entries = NUMBER: Sets the maximum amount of entries an archive can contain when imported. Defaults to 1000000. 0 disables the limit.")
         )
         .custom (
             Section::new("files")
@@ -19,30 +19,233 @@
 
 use crate::{
     common,
+    dedup::{self, Savings},
+    format::{self, Compression, Reader, Writer},
+    inv::{format_item, ItemKind},
     message::{append_emoji, EmojiType},
+    meta::{self, Metadata},
+    progress::Progress,
+    snapshot::{self, FileState, Index, Kind, Snapshot},
+    store, trash,
 };
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use chrono::{Local, TimeZone};
 use colored::Colorize;
-use std::{fs::File, path::PathBuf};
-use tar::Archive;
-use xz2::read::XzDecoder;
-use xz2::write::XzEncoder;
-
-/// Exports an inventory slot into an xz tarball
-pub fn export_inv(slot: &str, output: PathBuf, message: bool) -> Result<()> {
-    let slotdir: PathBuf = match slot {
-        "active" | "a" => common::env_config()?.active_dir,
-        "inactive" | "i" => common::env_config()?.inactive_dir,
-        _ => PathBuf::new(),
-    };
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use size_format::SizeFormatterBinary;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+    time::{Duration, UNIX_EPOCH},
+};
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+use tar::{Entry, EntryType};
+
+/// Policies for items in an archive which share their name with an item in the slot
+#[derive(Clone, Copy)]
+pub enum Conflict {
+    Skip,
+    Rename,
+    Overwrite,
+    Newer,
+}
+
+impl FromStr for Conflict {
+    type Err = anyhow::Error;
+
+    fn from_str(policy: &str) -> Result<Self> {
+        match policy {
+            "skip" => Ok(Conflict::Skip),
+            "rename" => Ok(Conflict::Rename),
+            "overwrite" => Ok(Conflict::Overwrite),
+            "newer" => Ok(Conflict::Newer),
+            _ => bail!(
+                "{}",
+                format!(
+                    "No such conflict policy. Valid policies are {}, {}, {} and {}",
+                    "skip".bold(),
+                    "rename".bold(),
+                    "overwrite".bold(),
+                    "newer".bold()
+                )
+                .red()
+            ),
+        }
+    }
+}
+
+/// The name of the manifest inside of archives exported by Vento
+pub const MANIFEST: &str = ".vento-manifest";
+
+// What's shown when an archive can't be read through
+const UNREADABLE: &str = "Vento was unable to read the archive. It may be truncated or corrupted";
+
+/// The name of the directory inside the Vento directory which files are backed up into
+pub const BACKUPS: &str = "backups";
+
+/// What's left out of exports of the Vento directory, as it's only of use to the Vento directory it's in
+pub const EXCLUDED: &[&str] = &[
+    ".staging",
+    BACKUPS,
+    format::SPOOL,
+    snapshot::INDEX,
+    trash::TRASH,
+    dedup::OBJECTS,
+];
+
+/// Describes an archive exported by Vento, along with what its contents should hash to
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    pub slot: Option<String>,
+    pub time: i64,
+    pub files: Vec<ManifestFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<ManifestSnapshot>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, Metadata>,
+}
+
+/// Where an export of the Vento directory stands in a chain of exports
+#[derive(Serialize, Deserialize)]
+pub struct ManifestSnapshot {
+    pub id: String,
+    pub kind: Kind,
+    pub base: Option<String>,
+    pub removed: Vec<String>,
+}
+
+/// A file listed inside of a manifest
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Manifest {
+    fn new(slot: Option<&str>) -> Manifest {
+        Manifest {
+            version: String::from(env!("CARGO_PKG_VERSION")),
+            slot: slot.map(|slot| {
+                String::from(match slot {
+                    "a" | "active" => "active",
+                    "i" | "inactive" => "inactive",
+                    _ => slot,
+                })
+            }),
+            time: Local::now().timestamp(),
+            files: vec![],
+            snapshot: None,
+            metadata: BTreeMap::new(),
+        }
+    }
+}
+
+// Hashes everything read through it
+struct Hashing<R: Read> {
+    inner: R,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<R: Read> Hashing<R> {
+    fn new(inner: R) -> Hashing<R> {
+        Hashing {
+            inner,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    fn finish(self, path: &Path) -> ManifestFile {
+        ManifestFile {
+            path: format::entry_name(path),
+            size: self.size,
+            sha256: self
+                .hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        }
+    }
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.size += read as u64;
+        Ok(read)
+    }
+}
+
+// An entry inside of an archive
+struct Listing {
+    path: PathBuf,
+    kind: ItemKind,
+    size: u64,
+    mtime: u64,
+}
+
+// What happens to an item from an archive when imported
+enum Outcome {
+    Added,
+    Renamed(String),
+    Skipped,
+    Replaced,
+    Merged,
+}
+
+/// Exports an inventory slot into an archive
+pub fn export_inv(
+    slot: &str,
+    output: PathBuf,
+    compression: &Compression,
+    message: bool,
+) -> Result<()> {
+    let slotdir = store::open_dir(slot)?;
+
+    // Files are hashed before they're written, so the manifest can go first and survive a truncated archive
+    let mut files: BTreeMap<String, ManifestFile> = BTreeMap::new();
+    let mut progress = Progress::measure("Hashing", &slotdir);
+    hash_tree(
+        &slotdir,
+        Path::new(""),
+        &[],
+        None,
+        &mut files,
+        &mut progress,
+    )?;
+    progress.finish();
+
+    let mut writer = Writer::create(&output, compression)?;
+    let mut manifest = Manifest::new(Some(slot));
+    manifest.metadata = meta::slot(slot)?;
+    manifest.files = files.values().cloned().collect();
+    writer.append_data(Path::new(MANIFEST), &serde_json::to_vec_pretty(&manifest)?)?;
 
-    let archive = File::create(&output)?;
-    let enc = XzEncoder::new(archive, 9);
-    let mut tar = tar::Builder::new(enc);
-    tar.append_dir_all("", slotdir)?;
+    let mut progress = Progress::measure("Exporting", &slotdir);
+    append_tree(
+        &mut writer,
+        &slotdir,
+        Path::new(""),
+        &[],
+        None,
+        &files,
+        &mut progress,
+    )?;
+    writer.finish()?;
+    progress.finish();
 
     if message {
-        println!(
+        let text = format!(
             "{}{} {} {} {}",
             append_emoji(EmojiType::Success)?,
             "Exported".green(),
@@ -53,51 +256,246 @@ pub fn export_inv(slot: &str, output: PathBuf, message: bool) -> Result<()> {
             }
             .bold(),
             "slot into".green(),
-            &output.to_str().unwrap()
+            display_path(&output, "standard output")
         );
+        print_export(&output, &text);
     };
     Ok(())
 }
 
-/// Exports the Vento directory into an xz tarball
-pub fn export_dir(output: PathBuf, message: bool) -> Result<()> {
+/// Exports the Vento directory into an archive, either whole or only what changed since a previous export.
+/// Unless recording is turned off, as done for snapshots and backups, later exports can be based on this one
+pub fn export_dir(
+    output: PathBuf,
+    compression: &Compression,
+    kind: Kind,
+    record: bool,
+    message: bool,
+) -> Result<()> {
     let dir: PathBuf = common::env_config()?.vento_dir;
+    let mut index = Index::load()?;
+    let mut files: BTreeMap<String, FileState> = BTreeMap::new();
+    snapshot::state(&dir, "", EXCLUDED, &mut files)?;
+
+    // Files which didn't change since the export this one is based on are left out
+    let (base, changed, removed) = match index.base(kind)? {
+        Some(base) => {
+            let changed: BTreeSet<String> = files
+                .iter()
+                .filter(|(path, state)| base.files.get(*path) != Some(*state))
+                .map(|(path, _)| path.clone())
+                .collect();
+            let removed: Vec<String> = base
+                .files
+                .keys()
+                .filter(|path| !files.contains_key(*path))
+                .cloned()
+                .collect();
+            (Some(base.id.clone()), Some(changed), removed)
+        }
+        None => (None, None, vec![]),
+    };
+
+    let exported: Vec<&FileState> = files
+        .iter()
+        .filter(|(path, _)| {
+            changed
+                .as_ref()
+                .is_none_or(|changed| changed.contains(*path))
+        })
+        .map(|(_, state)| state)
+        .collect();
+    let total_files = exported.len() as u64;
+    let total_bytes: u64 = exported.iter().map(|state| state.size).sum();
+
+    // Files are hashed before they're written, so the manifest can go first and survive a truncated archive
+    let mut hashes: BTreeMap<String, ManifestFile> = BTreeMap::new();
+    let mut progress = Progress::new("Hashing", Some(total_files), Some(total_bytes));
+    hash_tree(
+        &dir,
+        Path::new(""),
+        EXCLUDED,
+        changed.as_ref(),
+        &mut hashes,
+        &mut progress,
+    )?;
+    progress.finish();
 
-    let archive = File::create(&output)?;
-    let enc = XzEncoder::new(archive, 9);
-    let mut tar = tar::Builder::new(enc);
-    tar.append_dir_all("", dir)?;
+    let id = index.next_id();
+    let mut writer = Writer::create(&output, compression)?;
+    let mut manifest = Manifest::new(None);
+    manifest.snapshot = Some(ManifestSnapshot {
+        id: id.clone(),
+        kind,
+        base: base.clone(),
+        removed: removed.clone(),
+    });
+    manifest.files = hashes.values().cloned().collect();
+    writer.append_data(Path::new(MANIFEST), &serde_json::to_vec_pretty(&manifest)?)?;
+
+    let mut progress = Progress::new("Exporting", Some(total_files), Some(total_bytes));
+    append_tree(
+        &mut writer,
+        &dir,
+        Path::new(""),
+        EXCLUDED,
+        changed.as_ref(),
+        &hashes,
+        &mut progress,
+    )?;
+    writer.finish()?;
+    progress.finish();
+
+    if record {
+        index.snapshots.push(Snapshot {
+            id,
+            kind,
+            base,
+            time: manifest.time,
+            files,
+        });
+        index.save()?;
+    }
 
     if message {
-        println!(
-            "{}{} {}",
-            append_emoji(EmojiType::Success)?,
-            "Exported Vento directory into".green(),
-            &output.to_str().unwrap()
-        );
+        let text = match kind {
+            Kind::Full => format!(
+                "{}{} {}",
+                append_emoji(EmojiType::Success)?,
+                "Exported Vento directory into".green(),
+                display_path(&output, "standard output")
+            ),
+            _ => format!(
+                "{}{} {} {} {} {}",
+                append_emoji(EmojiType::Success)?,
+                format!("Exported {} changes to the Vento directory into", kind).green(),
+                display_path(&output, "standard output"),
+                format!("({} changed,", manifest.files.len()).green(),
+                removed.len().to_string().green(),
+                "removed)".green()
+            ),
+        };
+        print_export(&output, &text);
     };
     Ok(())
 }
 
-/// Imports an xz tarball into an inventory slot
-pub fn import_inv(input: PathBuf, slot: &str, message: bool) -> Result<()> {
-    let slotdir: PathBuf = match slot {
-        "active" | "a" => common::env_config()?.active_dir,
-        "inactive" | "i" => common::env_config()?.inactive_dir,
-        _ => PathBuf::new(),
+/// Prints a message about an export, keeping it out of the archive if it's being streamed into the standard output
+fn print_export(output: &Path, text: &str) {
+    match format::is_stdio(output) {
+        true => eprintln!("{}", text),
+        false => println!("{}", text),
+    }
+}
+
+/// Displays the path of an archive, or what it stands for if it's "-"
+fn display_path(path: &Path, stdio: &str) -> String {
+    match format::is_stdio(path) {
+        true => String::from(stdio),
+        false => path.to_string_lossy().to_string(),
+    }
+}
+
+/// Adds every file and directory inside a directory into an archive, skipping the names excluded.
+/// If a set of files is given, only those are added along with every directory.
+/// Files are checked against the hashes taken for the manifest, so one changing midway isn't silently exported
+fn append_tree(
+    writer: &mut Writer,
+    dir: &Path,
+    prefix: &Path,
+    exclude: &[&str],
+    only: Option<&BTreeSet<String>>,
+    hashes: &BTreeMap<String, ManifestFile>,
+    progress: &mut Progress,
+) -> Result<()> {
+    let mut items: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    items.sort_by_key(|item| item.file_name());
+
+    for item in items {
+        let name = item.file_name();
+        if exclude.iter().any(|excluded| name == *excluded) {
+            continue;
+        }
+
+        let source = item.path();
+        let path = prefix.join(&name);
+        let metadata = fs::symlink_metadata(&source)?;
+
+        if metadata.is_dir() {
+            writer.append_dir(&path, &metadata)?;
+            append_tree(writer, &source, &path, &[], only, hashes, progress)?;
+        } else if !only.is_none_or(|only| only.contains(&format::entry_name(&path))) {
+            continue;
+        } else if metadata.file_type().is_symlink() {
+            // Symlinks are kept as they are, instead of adding whatever they point to
+            writer.append_symlink(&path, &metadata, &fs::read_link(&source)?)?;
+            progress.add_file();
+        } else {
+            // Hashes the file while it's written, so it only needs to be read once
+            let mut contents = Hashing::new(progress.reader(File::open(&source)?));
+            writer.append_file(&path, &metadata, &mut contents)?;
+            let file = contents.finish(&path);
+            if hashes.get(&file.path) != Some(&file) {
+                bail!(
+                    "{}",
+                    format!(
+                        "{} changed while it was being exported. Aborting export",
+                        file.path.bold()
+                    )
+                    .red()
+                );
+            }
+            progress.add_file();
+        }
+    }
+
+    Ok(())
+}
+
+/// Imports an archive into an inventory slot
+pub fn import_inv(
+    input: PathBuf,
+    slot: &str,
+    conflict: Option<Conflict>,
+    item: Option<&str>,
+    key: Option<&Path>,
+    message: bool,
+) -> Result<()> {
+    let slotdir = store::open_dir(slot)?;
+
+    // Unpacks into a staging directory first, so nothing reaches the slot unless it's been verified
+    let dir = common::env_config()?.vento_dir;
+    let (staging, manifest) = stage(&input, &dir, item, key, message)?;
+    if let Err(error) = snapshot::auto(message) {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(error);
+    }
+    let merged = match conflict {
+        Some(conflict) => merge(&staging, &slotdir, conflict, message),
+        None => names(&staging).and_then(|names| overlay(&staging, &slotdir).map(|_| names)),
     };
+    // Failing to clean up the staging directory shouldn't hide whether the merge itself worked
+    let _ = fs::remove_dir_all(&staging);
+    let placed = merged?;
+    if dedup::enabled()? {
+        dedup::store(&slotdir, &mut Savings::default())?;
+    }
 
-    let tar_xz = File::open(&input)?;
-    let tar = XzDecoder::new(tar_xz);
-    let mut archive = Archive::new(tar);
-    archive.unpack(&slotdir)?;
+    // Items keep their tags and notes, under whatever name they ended up with
+    if let Some(manifest) = manifest {
+        for (name, placed) in placed {
+            if let Some(metadata) = manifest.metadata.get(&name) {
+                meta::set(slot, &placed, metadata)?;
+            }
+        }
+    }
 
     if message {
         println!(
             "{}{} {} {} {} {}",
             append_emoji(EmojiType::Success)?,
             "Imported".green(),
-            &input.to_str().unwrap(),
+            display_path(&input, "standard input"),
             "into".green(),
             match slot {
                 "a" | "active" => "active".green(),
@@ -111,23 +509,1168 @@ pub fn import_inv(input: PathBuf, slot: &str, message: bool) -> Result<()> {
     Ok(())
 }
 
-/// Imports an xz tarball into the Vento directory
-pub fn import_dir(input: PathBuf, message: bool) -> Result<()> {
+/// Shows what importing an archive into an inventory slot would do, without importing it
+pub fn preview_inv(
+    input: PathBuf,
+    slot: &str,
+    conflict: Option<Conflict>,
+    item: Option<&str>,
+    key: Option<&Path>,
+) -> Result<()> {
+    let slotdir = store::open_dir(slot)?;
+
+    // Collects the items at the top of the archive along with their newest modification time
+    let mut items: BTreeMap<String, u64> = BTreeMap::new();
+    let (listings, _) = scan(Reader::open(&input, key)?)?;
+    for listing in listings {
+        let name = match top_level(&listing.path) {
+            Some(name) => name,
+            None => continue,
+        };
+        if item.is_some_and(|item| item != name) {
+            continue;
+        }
+        let newest = items.entry(name).or_insert(0);
+        if !matches!(listing.kind, ItemKind::Directory) {
+            *newest = (*newest).max(listing.mtime);
+        }
+    }
+
+    println!(
+        "{}{}",
+        append_emoji(EmojiType::Inventory)?,
+        format!(
+            "Importing {} into {} would:",
+            display_path(&input, "standard input"),
+            match slot {
+                "a" | "active" => "active".green(),
+                "i" | "inactive" => "inactive".blue(),
+                _ => slot.red(),
+            }
+            .bold()
+        )
+        .green()
+    );
+
+    for (name, mtime) in items {
+        let existing = slotdir.join(&name);
+        let outcome = if fs::symlink_metadata(&existing).is_err() {
+            Outcome::Added
+        } else {
+            match conflict {
+                None => Outcome::Merged,
+                Some(Conflict::Skip) => Outcome::Skipped,
+                Some(Conflict::Rename) => Outcome::Renamed(free_name(&slotdir, &name)),
+                Some(Conflict::Overwrite) => Outcome::Replaced,
+                Some(Conflict::Newer) => {
+                    if mtime > modified(&existing)? {
+                        Outcome::Replaced
+                    } else {
+                        Outcome::Skipped
+                    }
+                }
+            }
+        };
+        print_outcome(&name, &outcome, true);
+    }
+
+    Ok(())
+}
+
+/// Lists the items inside an archive, without importing it
+pub fn list(input: PathBuf, key: Option<&Path>) -> Result<()> {
+    let (listings, manifest) = scan(Reader::open(&input, key)?)?;
+
+    // Folds every entry into the item at the top of the archive it belongs to
+    let mut items: BTreeMap<String, (ItemKind, Option<u64>)> = BTreeMap::new();
+    for listing in listings {
+        let name = match top_level(&listing.path) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        if listing.path.components().count() == 1 {
+            items.insert(
+                name,
+                (
+                    listing.kind,
+                    match listing.kind {
+                        ItemKind::File => Some(listing.size),
+                        _ => None,
+                    },
+                ),
+            );
+        } else {
+            items.entry(name).or_insert((ItemKind::Directory, None));
+        }
+    }
+
+    if let Some(manifest) = &manifest {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Inventory)?,
+            format!("Archive of the {}", describe(manifest)).green()
+        );
+    }
+
+    if items.is_empty() {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Inventory)?,
+            format!("No files in {}", display_path(&input, "standard input")).green()
+        );
+    } else {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Inventory)?,
+            format!(
+                "Files in {} ({}):",
+                display_path(&input, "standard input"),
+                format!("{}", items.len()).white().bold()
+            )
+            .green()
+        );
+        for (name, (kind, size)) in items {
+            println!(
+                "{}{}",
+                format_item(kind, &name, size),
+                match manifest.as_ref().and_then(|m| m.metadata.get(&name)) {
+                    Some(metadata) => metadata.describe(),
+                    None => String::new(),
+                }
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Imports an archive into the Vento directory
+pub fn import_dir(input: PathBuf, key: Option<&Path>, message: bool) -> Result<()> {
     let dir: PathBuf = common::env_config()?.vento_dir;
 
-    let tar_xz = File::open(&input)?;
-    let tar = XzDecoder::new(tar_xz);
-    let mut archive = Archive::new(tar);
-    archive.unpack(&dir)?;
+    let (staging, manifest) = stage(&input, &dir, None, key, message)?;
+    if let Err(error) = snapshot::auto(message) {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(error);
+    }
+
+    // Incremental and differential exports also carry what was removed since their base, which is removed here too
+    let mut dirs: BTreeSet<PathBuf> = BTreeSet::new();
+    let merged =
+        list_dirs(&staging, Path::new(""), &mut dirs).and_then(|_| overlay(&staging, &dir));
+    let _ = fs::remove_dir_all(&staging);
+    merged?;
+    if let Some(snapshot) = manifest.and_then(|manifest| manifest.snapshot) {
+        remove(&dir, &snapshot.removed, &dirs)?;
+    }
+    if dedup::enabled()? {
+        dedup::dedup(false)?;
+    }
 
     if message {
         println!(
             "{}{} {} {}",
             append_emoji(EmojiType::Success)?,
             "Imported".green(),
-            &input.to_str().unwrap(),
+            display_path(&input, "standard input"),
             "into Vento directory".green(),
         );
     };
     Ok(())
 }
+
+/// Checks an archive's contents against the manifest embedded in it
+pub fn verify(input: PathBuf, key: Option<&Path>) -> Result<()> {
+    let (files, manifest) =
+        hash_archive(Reader::open(&input, key)?).context(UNREADABLE.red().to_string())?;
+
+    let manifest = match manifest {
+        Some(manifest) => manifest,
+        None => bail!(
+            "{}",
+            "Archive has no manifest, so its contents can't be verified".red()
+        ),
+    };
+    check_manifest(&manifest, &files)?;
+
+    println!(
+        "{}{}",
+        append_emoji(EmojiType::Success)?,
+        format!(
+            "Verified {} files in {} ({})",
+            manifest.files.len().to_string().white().bold(),
+            display_path(&input, "standard input"),
+            describe(&manifest)
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+/// Hashes every file inside an archive without unpacking it, also returning its manifest if found
+fn hash_archive(reader: Reader) -> Result<(BTreeMap<String, ManifestFile>, Option<Manifest>)> {
+    let mut files: BTreeMap<String, ManifestFile> = BTreeMap::new();
+    let mut manifest: Option<Manifest> = None;
+
+    match reader {
+        Reader::Tar(mut archive) => {
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let path = entry.path()?.into_owned();
+
+                // Hard links hash just like the file they point to, as they would once unpacked
+                if entry.header().entry_type() == EntryType::Link {
+                    let target = format::entry_name(&entry.link_name()?.unwrap_or_default());
+                    if let Some(file) = files.get(&target) {
+                        let file = ManifestFile {
+                            path: format::entry_name(&path),
+                            ..file.clone()
+                        };
+                        files.insert(file.path.clone(), file);
+                    }
+                    continue;
+                }
+                if !matches!(
+                    entry.header().entry_type(),
+                    EntryType::Regular | EntryType::Continuous
+                ) {
+                    continue;
+                }
+
+                if path == Path::new(MANIFEST) {
+                    manifest = Some(serde_json::from_reader(&mut entry)?);
+                } else {
+                    let mut contents = Hashing::new(&mut entry);
+                    io::copy(&mut contents, &mut io::sink())?;
+                    let file = contents.finish(&path);
+                    files.insert(file.path.clone(), file);
+                }
+            }
+        }
+        Reader::Zip(mut archive) => {
+            for index in 0..archive.len() {
+                let mut file = archive.by_index(index)?;
+                if file.is_dir() || file.is_symlink() {
+                    continue;
+                }
+                let path = PathBuf::from(file.name());
+
+                if path == Path::new(MANIFEST) {
+                    manifest = Some(serde_json::from_reader(&mut file)?);
+                } else {
+                    let mut contents = Hashing::new(&mut file);
+                    io::copy(&mut contents, &mut io::sink())?;
+                    let file = contents.finish(&path);
+                    files.insert(file.path.clone(), file);
+                }
+            }
+        }
+    }
+
+    Ok((files, manifest))
+}
+
+/// Unpacks an archive into a staging directory inside another one and verifies it, returning the directory along with the archive's manifest
+fn stage(
+    input: &Path,
+    dir: &Path,
+    only: Option<&str>,
+    key: Option<&Path>,
+    message: bool,
+) -> Result<(PathBuf, Option<Manifest>)> {
+    // Opened before staging, so a wrong passphrase or key isn't taken for a corrupted archive
+    let reader = Reader::open(input, key)?;
+    let staging = staging_dir(dir)?;
+
+    match verify_staged(reader, &staging, only, message) {
+        Ok(manifest) => Ok((staging, manifest)),
+        Err(error) => {
+            let _ = fs::remove_dir_all(&staging);
+            Err(error)
+        }
+    }
+}
+
+// Unpacks an archive into the staging directory, comparing the files in it with its manifest
+fn verify_staged(
+    reader: Reader,
+    staging: &Path,
+    only: Option<&str>,
+    message: bool,
+) -> Result<Option<Manifest>> {
+    unpack(reader, staging, only, message).context(UNREADABLE.red().to_string())?;
+
+    if let Some(only) = only {
+        if fs::symlink_metadata(staging.join(only)).is_err() {
+            bail!(
+                "{}",
+                format!("No item named {} in archive", only.bold()).red()
+            );
+        }
+    }
+
+    let manifest_path = staging.join(MANIFEST);
+    if !manifest_path.is_file() {
+        if message {
+            println!(
+                "{}{}",
+                append_emoji(EmojiType::Warning)?,
+                "Archive has no manifest, so its contents weren't verified".yellow()
+            );
+        }
+        return Ok(None);
+    }
+
+    let mut manifest: Manifest = serde_json::from_slice(&fs::read(&manifest_path)?)?;
+    fs::remove_file(&manifest_path)?;
+
+    if let Some(only) = only {
+        manifest
+            .files
+            .retain(|file| top_level(Path::new(&file.path)).as_deref() == Some(only));
+    }
+
+    let mut files: BTreeMap<String, ManifestFile> = BTreeMap::new();
+    let mut progress = Progress::measure("Verifying", staging);
+    hash_tree(staging, Path::new(""), &[], None, &mut files, &mut progress)?;
+    progress.finish();
+    check_manifest(&manifest, &files)?;
+    Ok(Some(manifest))
+}
+
+/// Restores a chain of Vento directory exports into an empty directory, starting with a full export and followed by the ones based on it
+pub fn restore(
+    dest: PathBuf,
+    inputs: Vec<PathBuf>,
+    key: Option<&Path>,
+    message: bool,
+) -> Result<()> {
+    if dest.exists() && fs::read_dir(&dest)?.next().is_some() {
+        bail!(
+            "{}",
+            format!(
+                "{} isn't empty. Exports can only be restored into an empty directory",
+                dest.display()
+            )
+            .red()
+        );
+    }
+    fs::create_dir_all(&dest)?;
+
+    let mut full: Option<String> = None;
+    let mut previous: Option<String> = None;
+    let mut index = Index::default();
+
+    for input in &inputs {
+        // Staged inside the destination, so the files can be moved into place without crossing filesystems
+        let (staging, manifest) = stage(input, &dest, None, key, message)?;
+        let time = manifest.as_ref().map_or(0, |manifest| manifest.time);
+        let link = match manifest {
+            None => Err(String::from(
+                "has no manifest to tell where it stands in the chain",
+            )),
+            Some(Manifest { slot: Some(_), .. }) => {
+                Err(String::from("is an export of an inventory slot"))
+            }
+            Some(Manifest { snapshot: None, .. }) => Err(String::from(
+                "was exported by an older version of Vento, which can't be chained",
+            )),
+            Some(Manifest {
+                snapshot: Some(snapshot),
+                ..
+            }) => match (snapshot.kind, &snapshot.base) {
+                (Kind::Full, _) if previous.is_none() => Ok(snapshot),
+                (Kind::Full, _) => Err(String::from(
+                    "is a full export, which can only start a chain",
+                )),
+                (_, _) if previous.is_none() => Err(String::from(
+                    "isn't a full export, which a chain has to start with",
+                )),
+                (Kind::Incremental, base) if base == &previous => Ok(snapshot),
+                (Kind::Differential, base) if base == &full => Ok(snapshot),
+                (_, _) => Err(String::from(
+                    "is based on a different export than the ones before it",
+                )),
+            },
+        };
+
+        let snapshot = match link {
+            Ok(snapshot) => snapshot,
+            Err(problem) => {
+                let _ = fs::remove_dir_all(&staging);
+                bail!(
+                    "{}",
+                    format!(
+                        "{} {}. Restoring stopped before it",
+                        display_path(input, "standard input"),
+                        problem
+                    )
+                    .red()
+                );
+            }
+        };
+
+        // Every directory still around when the export was made is in it, even the empty ones
+        let mut dirs: BTreeSet<PathBuf> = BTreeSet::new();
+        let merged =
+            list_dirs(&staging, Path::new(""), &mut dirs).and_then(|_| overlay(&staging, &dest));
+        let _ = fs::remove_dir_all(&staging);
+        merged?;
+
+        remove(&dest, &snapshot.removed, &dirs)?;
+
+        // The restored directory gets its own index, so exports made from it can keep the chain going
+        let mut files: BTreeMap<String, FileState> = BTreeMap::new();
+        snapshot::state(&dest, "", EXCLUDED, &mut files)?;
+        index.snapshots.push(Snapshot {
+            id: snapshot.id.clone(),
+            kind: snapshot.kind,
+            base: snapshot.base.clone(),
+            time,
+            files,
+        });
+
+        if message {
+            println!(
+                "{}{} {} {}",
+                append_emoji(EmojiType::Success)?,
+                "Restored".green(),
+                display_path(input, "standard input"),
+                format!("({} export {})", snapshot.kind, snapshot.id).green()
+            );
+        }
+
+        if snapshot.kind == Kind::Full {
+            full = Some(snapshot.id.clone());
+        }
+        previous = Some(snapshot.id);
+    }
+    index.save_into(&dest)?;
+
+    if message {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Success)?,
+            format!("Restored {} exports into {}", inputs.len(), dest.display()).green()
+        );
+    }
+    Ok(())
+}
+
+// Lists every directory inside a directory, along with the ones inside of them
+fn list_dirs(dir: &Path, prefix: &Path, dirs: &mut BTreeSet<PathBuf>) -> Result<()> {
+    for item in fs::read_dir(dir)? {
+        let item = item?;
+        if item.file_type()?.is_dir() {
+            let path = prefix.join(item.file_name());
+            list_dirs(&item.path(), &path, dirs)?;
+            dirs.insert(path);
+        }
+    }
+    Ok(())
+}
+
+/// Hashes every file inside a directory, for listing them in a manifest or comparing them against one.
+/// Names excluded are skipped, and if a set of files is given, only those are hashed
+fn hash_tree(
+    dir: &Path,
+    prefix: &Path,
+    exclude: &[&str],
+    only: Option<&BTreeSet<String>>,
+    files: &mut BTreeMap<String, ManifestFile>,
+    progress: &mut Progress,
+) -> Result<()> {
+    for item in fs::read_dir(dir)? {
+        let item = item?;
+        let name = item.file_name();
+        if exclude.iter().any(|excluded| name == *excluded) {
+            continue;
+        }
+
+        let path = prefix.join(&name);
+        let file_type = item.file_type()?;
+
+        if file_type.is_dir() {
+            hash_tree(&item.path(), &path, &[], only, files, progress)?;
+        } else if file_type.is_file()
+            && only.is_none_or(|only| only.contains(&format::entry_name(&path)))
+        {
+            let mut contents = Hashing::new(progress.reader(File::open(item.path())?));
+            io::copy(&mut contents, &mut io::sink())?;
+            let file = contents.finish(&path);
+            files.insert(file.path.clone(), file);
+            progress.add_file();
+        }
+    }
+    Ok(())
+}
+
+/// Compares the files found in an archive with the ones its manifest lists, bailing on any difference
+fn check_manifest(manifest: &Manifest, files: &BTreeMap<String, ManifestFile>) -> Result<()> {
+    let mut problems: Vec<(String, &str)> = vec![];
+    let mut listed: BTreeSet<&str> = BTreeSet::new();
+
+    for expected in &manifest.files {
+        listed.insert(&expected.path);
+        match files.get(&expected.path) {
+            None => problems.push((expected.path.clone(), "missing")),
+            Some(found) if found.size != expected.size => {
+                problems.push((expected.path.clone(), "size mismatch"))
+            }
+            Some(found) if found.sha256 != expected.sha256 => {
+                problems.push((expected.path.clone(), "checksum mismatch"))
+            }
+            Some(_) => (),
+        }
+    }
+
+    for path in files.keys() {
+        if !listed.contains(path.as_str()) {
+            problems.push((path.clone(), "not in manifest"));
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    for (path, problem) in &problems {
+        eprintln!(
+            "{}{} {}",
+            append_emoji(EmojiType::Warning)?,
+            path.bold(),
+            format!("({})", problem).red()
+        );
+    }
+    bail!(
+        "{}",
+        format!(
+            "Archive failed verification. {} files don't match its manifest",
+            problems.len()
+        )
+        .red()
+    );
+}
+
+/// Describes where an archive comes from, using its manifest
+fn describe(manifest: &Manifest) -> String {
+    let date = match Local.timestamp_opt(manifest.time, 0).single() {
+        Some(date) => date.format("%Y-%m-%d, %H:%M:%S").to_string(),
+        None => String::from("unknown date"),
+    };
+
+    format!(
+        "{}, exported on {} by Vento {}",
+        match (&manifest.slot, &manifest.snapshot) {
+            (Some(slot), _) => format!("{} slot", slot),
+            (None, Some(snapshot)) if snapshot.kind != Kind::Full => {
+                format!("Vento directory ({} changes)", snapshot.kind)
+            }
+            (None, _) => String::from("Vento directory"),
+        },
+        date,
+        manifest.version
+    )
+}
+
+/// Moves everything from a staging directory into another one, replacing whatever's in the way
+fn overlay(staging: &Path, dest: &Path) -> Result<()> {
+    fs::create_dir_all(dest)?;
+
+    for item in fs::read_dir(staging)? {
+        let item = item?;
+        let source = item.path();
+        let target = dest.join(item.file_name());
+
+        match fs::symlink_metadata(&target) {
+            Ok(metadata) if metadata.is_dir() && item.file_type()?.is_dir() => {
+                overlay(&source, &target)?
+            }
+            Ok(metadata) => {
+                if metadata.is_dir() {
+                    fs::remove_dir_all(&target)?;
+                } else {
+                    fs::remove_file(&target)?;
+                }
+                fs::rename(&source, &target)?;
+            }
+            Err(_) => fs::rename(&source, &target)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the files an export recorded as gone since the one it's based on
+fn remove(dest: &Path, removed: &[String], dirs: &BTreeSet<PathBuf>) -> Result<()> {
+    for removed in removed {
+        let path = Path::new(removed);
+        if path
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            continue;
+        }
+        match fs::symlink_metadata(dest.join(path)) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(dest.join(path))?,
+            Ok(_) => fs::remove_file(dest.join(path))?,
+            Err(_) => (),
+        }
+
+        // Directories left empty by the removal are gone too, unless the export still had them
+        let mut parent = path.parent();
+        while let Some(dir) = parent.filter(|dir| dir != &Path::new("") && !dirs.contains(*dir)) {
+            if fs::remove_dir(dest.join(dir)).is_err() {
+                break;
+            }
+            parent = dir.parent();
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves every item from a staging directory into a slot, resolving name collisions with a policy.
+/// Returns the name each item placed in the slot ended up with
+fn merge(
+    staging: &Path,
+    slotdir: &Path,
+    conflict: Conflict,
+    message: bool,
+) -> Result<BTreeMap<String, String>> {
+    let mut backup: Option<PathBuf> = None;
+    let (mut added, mut skipped, mut replaced) = (0, 0, 0);
+    let mut placed = BTreeMap::new();
+
+    for name in names(staging)?.into_keys() {
+        let source = staging.join(&name);
+        let existing = slotdir.join(&name);
+
+        let outcome = if fs::symlink_metadata(&existing).is_err() {
+            Outcome::Added
+        } else {
+            match conflict {
+                Conflict::Skip => Outcome::Skipped,
+                Conflict::Rename => Outcome::Renamed(free_name(slotdir, &name)),
+                Conflict::Overwrite => Outcome::Replaced,
+                Conflict::Newer => {
+                    if modified(&source)? > modified(&existing)? {
+                        Outcome::Replaced
+                    } else {
+                        Outcome::Skipped
+                    }
+                }
+            }
+        };
+
+        match &outcome {
+            Outcome::Added => {
+                fs::rename(&source, &existing)?;
+                placed.insert(name.clone(), name.clone());
+                added += 1;
+            }
+            Outcome::Renamed(renamed) => {
+                fs::rename(&source, slotdir.join(renamed))?;
+                placed.insert(name.clone(), renamed.clone());
+                added += 1;
+            }
+            Outcome::Replaced => {
+                // Keeps whatever gets overwritten, in case the archive wasn't the right one
+                let backupdir = match &backup {
+                    Some(dir) => dir.clone(),
+                    None => {
+                        let dir = backup_dir("import")?;
+                        backup = Some(dir.clone());
+                        dir
+                    }
+                };
+                fs::rename(&existing, backupdir.join(&name))?;
+                fs::rename(&source, &existing)?;
+                placed.insert(name.clone(), name.clone());
+                replaced += 1;
+            }
+            Outcome::Skipped | Outcome::Merged => skipped += 1,
+        }
+
+        if message {
+            print_outcome(&name, &outcome, false);
+        }
+    }
+
+    if message {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Inventory)?,
+            format!(
+                "{} added, {} skipped, {} replaced",
+                added.to_string().white().bold(),
+                skipped.to_string().white().bold(),
+                replaced.to_string().white().bold()
+            )
+            .green()
+        );
+        if let Some(dir) = backup {
+            println!(
+                "{}{} {}",
+                append_emoji(EmojiType::Warning)?,
+                "Replaced items were backed up into".yellow(),
+                dir.to_str().unwrap()
+            );
+        }
+    }
+
+    Ok(placed)
+}
+
+// Lists the items at the top of a staging directory, each keeping its name once moved into a slot
+fn names(staging: &Path) -> Result<BTreeMap<String, String>> {
+    fs::read_dir(staging)?
+        .map(|item| {
+            let name = item?.file_name().to_string_lossy().to_string();
+            Ok((name.clone(), name))
+        })
+        .collect()
+}
+
+/// Prints what happened, or would happen, to an item when importing it
+fn print_outcome(name: &str, outcome: &Outcome, preview: bool) {
+    let (verb, detail) = match outcome {
+        Outcome::Added => (if preview { "add" } else { "Added" }.green(), String::new()),
+        Outcome::Merged => (
+            if preview { "merge" } else { "Merged" }.yellow(),
+            String::new(),
+        ),
+        Outcome::Skipped => (
+            if preview { "skip" } else { "Skipped" }.yellow(),
+            String::new(),
+        ),
+        Outcome::Replaced => (
+            if preview { "replace" } else { "Replaced" }.red(),
+            String::new(),
+        ),
+        Outcome::Renamed(renamed) => (
+            if preview { "add" } else { "Added" }.green(),
+            format!(" {} {}", "as".green(), renamed.bold()),
+        ),
+    };
+
+    println!("   - {} {}{}", verb, name.bold(), detail);
+}
+
+/// Finds a name which isn't taken in a directory, appending a number to the original one
+fn free_name(dir: &Path, name: &str) -> String {
+    let path = Path::new(name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = match path.extension() {
+        Some(extension) => format!(".{}", extension.to_string_lossy()),
+        None => String::new(),
+    };
+
+    let mut number = 1;
+    loop {
+        let candidate = format!("{} ({}){}", stem, number, extension);
+        if fs::symlink_metadata(dir.join(&candidate)).is_err() {
+            return candidate;
+        }
+        number += 1;
+    }
+}
+
+/// Gets the newest modification time of the files under a path in seconds, without following symlinks
+fn modified(path: &Path) -> Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+
+    // Directory times change whenever they're unpacked into, so only their contents are compared
+    if metadata.is_dir() {
+        let mut newest = 0;
+        for item in fs::read_dir(path)? {
+            newest = newest.max(modified(&item?.path())?);
+        }
+        Ok(newest)
+    } else {
+        Ok(metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs())
+    }
+}
+
+/// Creates an empty staging directory inside the Vento directory to unpack archives into
+fn staging_dir(dir: &Path) -> Result<PathBuf> {
+    let staging = dir.join(".staging");
+
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    Ok(staging)
+}
+
+/// Creates a timestamped directory inside the Vento directory to back files up into
+pub fn backup_dir(reason: &str) -> Result<PathBuf> {
+    let dir = common::env_config()?.vento_dir.join(BACKUPS).join(format!(
+        "{}-{}",
+        reason,
+        Local::now().format("%Y%m%d-%H%M%S")
+    ));
+
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Lists the entries in an archive without unpacking them
+fn scan(reader: Reader) -> Result<(Vec<Listing>, Option<Manifest>)> {
+    let mut listings = vec![];
+    let mut manifest: Option<Manifest> = None;
+
+    match reader {
+        Reader::Tar(mut archive) => {
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let path = entry.path()?.into_owned();
+
+                if path == Path::new(MANIFEST) {
+                    manifest = serde_json::from_reader(&mut entry).ok();
+                    continue;
+                }
+
+                listings.push(Listing {
+                    path,
+                    kind: match entry.header().entry_type() {
+                        EntryType::Directory => ItemKind::Directory,
+                        EntryType::Symlink => ItemKind::Symlink,
+                        _ => ItemKind::File,
+                    },
+                    size: entry.size(),
+                    mtime: entry.header().mtime().unwrap_or(0),
+                });
+            }
+        }
+        Reader::Zip(mut archive) => {
+            for index in 0..archive.len() {
+                let mut file = archive.by_index(index)?;
+                let path = PathBuf::from(file.name());
+
+                if path == Path::new(MANIFEST) {
+                    manifest = serde_json::from_reader(&mut file).ok();
+                    continue;
+                }
+
+                listings.push(Listing {
+                    path,
+                    kind: if file.is_dir() {
+                        ItemKind::Directory
+                    } else if file.is_symlink() {
+                        ItemKind::Symlink
+                    } else {
+                        ItemKind::File
+                    },
+                    size: file.size(),
+                    mtime: format::zip_time(file.last_modified()),
+                });
+            }
+        }
+    }
+
+    Ok((listings, manifest))
+}
+
+/// Gets the name of the item at the top of the archive a path belongs to
+fn top_level(path: &Path) -> Option<String> {
+    match path
+        .components()
+        .find(|c| matches!(c, Component::Normal(_)))
+    {
+        Some(Component::Normal(name)) => Some(name.to_string_lossy().to_string()),
+        _ => None,
+    }
+}
+
+/// Unpacks an archive into a directory, refusing any entry which could write outside of it
+fn unpack(reader: Reader, dest: &Path, only: Option<&str>, message: bool) -> Result<()> {
+    let mut limits = Limits::new()?;
+    let mut refused: Vec<(String, &str)> = vec![];
+
+    // Picks out a single item from the archive, along with its manifest
+    let wanted = |path: &Path| -> bool {
+        match only {
+            Some(only) if path != Path::new(MANIFEST) => top_level(path).as_deref() == Some(only),
+            _ => true,
+        }
+    };
+
+    fs::create_dir_all(dest)?;
+    let root = fs::canonicalize(dest)?;
+
+    // Tar archives are read as they're unpacked, so how much is left is only known for whole zip archives
+    let mut progress = match (&reader, only) {
+        (Reader::Zip(archive), None) => Progress::new(
+            "Importing",
+            Some(archive.len() as u64),
+            archive.decompressed_size().map(|size| size as u64),
+        ),
+        _ => Progress::new("Importing", None, None),
+    };
+
+    match reader {
+        Reader::Tar(mut archive) => {
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let path = entry.path()?.display().to_string();
+                if !wanted(&entry.path()?) {
+                    continue;
+                }
+                limits.count_entry()?;
+
+                if let Some(reason) = check_entry(&entry)? {
+                    refused.push((path, reason));
+                    continue;
+                }
+                limits.count_size(entry.size())?;
+
+                if !entry.unpack_in(dest)? {
+                    refused.push((path, "resolves outside of the destination"));
+                    continue;
+                }
+                progress.add_bytes(entry.size());
+                progress.add_file();
+
+                if entry.header().entry_type() == EntryType::Symlink {
+                    // Symlinks are only checked once unpacked, as earlier ones may change where they land
+                    let link = dest.join(entry.path()?);
+                    let target = entry.link_name()?.unwrap_or_default();
+
+                    if !resolves_inside(&root, &link, &target)? {
+                        fs::remove_file(&link)?;
+                        refused.push((path, "symlink pointing outside of the destination"));
+                    }
+                }
+            }
+        }
+        Reader::Zip(mut archive) => {
+            for index in 0..archive.len() {
+                let mut file = archive.by_index(index)?;
+                let path = file.name().to_string();
+                if !wanted(Path::new(&path)) {
+                    continue;
+                }
+                progress.add_file();
+                limits.count_entry()?;
+
+                if let Some(reason) = check_path(Path::new(&path)) {
+                    refused.push((path, reason));
+                    continue;
+                }
+                let dest = dest.join(&path);
+
+                if file.is_dir() {
+                    fs::create_dir_all(&dest)?;
+                    continue;
+                }
+
+                // Zip archives store the target of a symlink as its contents
+                if file.is_symlink() {
+                    let mut target = String::new();
+                    file.read_to_string(&mut target)?;
+                    if Path::new(&target).has_root() {
+                        refused.push((path, "symlink to an absolute path"));
+                        continue;
+                    }
+
+                    if let Some(parent) = dest.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    #[cfg(unix)]
+                    std::os::unix::fs::symlink(&target, &dest)?;
+                    #[cfg(not(unix))]
+                    {
+                        refused.push((path, "symlinks are unsupported on this platform"));
+                        continue;
+                    }
+
+                    if !resolves_inside(&root, &dest, Path::new(&target))? {
+                        fs::remove_file(&dest)?;
+                        refused.push((path, "symlink pointing outside of the destination"));
+                    }
+                    continue;
+                }
+
+                // Earlier symlinks could still lead a file astray, so its parent gets checked too
+                let parent = dest.parent().unwrap_or(&root);
+                fs::create_dir_all(parent)?;
+                if !fs::canonicalize(parent)?.starts_with(&root) {
+                    refused.push((path, "resolves outside of the destination"));
+                    continue;
+                }
+
+                // Sizes in zip headers can't be trusted, so what's actually written is counted instead
+                let mut output = File::create(&dest)?;
+                let mut contents = progress
+                    .reader(&mut file)
+                    .take(limits.remaining().saturating_add(1));
+                limits.count_size(io::copy(&mut contents, &mut output)?)?;
+
+                let mtime = format::zip_time(file.last_modified());
+                output.set_modified(UNIX_EPOCH + Duration::from_secs(mtime))?;
+                #[cfg(unix)]
+                if let Some(mode) = file.unix_mode() {
+                    use std::os::unix::fs::PermissionsExt;
+                    output.set_permissions(fs::Permissions::from_mode(mode & 0o7777))?;
+                }
+            }
+        }
+    }
+
+    progress.finish();
+
+    if message {
+        for (path, reason) in &refused {
+            println!(
+                "{}{} {} {}",
+                append_emoji(EmojiType::Warning)?,
+                "Refused".yellow(),
+                path.bold(),
+                format!("({})", reason).yellow()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// Keeps track of how much an archive has unpacked, to guard against ones crafted to exhaust disk space or inodes
+struct Limits {
+    max_entries: u64,
+    max_size: u64,
+    entries: u64,
+    size: u64,
+}
+
+impl Limits {
+    fn new() -> Result<Limits> {
+        let config = common::parse_config()?;
+
+        Ok(Limits {
+            max_entries: config.archive_max_entries,
+            max_size: config.archive_max_size,
+            entries: 0,
+            size: 0,
+        })
+    }
+
+    fn count_entry(&mut self) -> Result<()> {
+        self.entries += 1;
+        if self.max_entries != 0 && self.entries > self.max_entries {
+            bail!(
+                "{}",
+                format!(
+                    "Archive has more than {} entries. Aborting import",
+                    self.max_entries
+                )
+                .red()
+            );
+        }
+        Ok(())
+    }
+
+    // How many more bytes can be unpacked before going over the limit
+    fn remaining(&self) -> u64 {
+        match self.max_size {
+            0 => u64::MAX,
+            max_size => max_size.saturating_sub(self.size),
+        }
+    }
+
+    fn count_size(&mut self, size: u64) -> Result<()> {
+        self.size = self.size.saturating_add(size);
+        if self.max_size != 0 && self.size > self.max_size {
+            bail!(
+                "{}",
+                format!(
+                    "Archive unpacks to more than {}B. Aborting import",
+                    SizeFormatterBinary::new(self.max_size)
+                )
+                .red()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Returns the reason a path inside of an archive can't be safely unpacked, if any
+fn check_path(path: &Path) -> Option<&'static str> {
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return Some("absolute path"),
+            Component::ParentDir => return Some("parent directory reference"),
+            Component::CurDir | Component::Normal(_) => (),
+        }
+    }
+    None
+}
+
+/// Returns the reason a tar entry can't be safely unpacked, if any
+fn check_entry<R: Read>(entry: &Entry<R>) -> Result<Option<&'static str>> {
+    if let Some(reason) = check_path(&entry.path()?) {
+        return Ok(Some(reason));
+    }
+
+    match entry.header().entry_type() {
+        EntryType::Regular | EntryType::Continuous | EntryType::Directory => Ok(None),
+        EntryType::Symlink => {
+            let target = entry.link_name()?.unwrap_or_default();
+
+            if target.has_root() {
+                Ok(Some("symlink to an absolute path"))
+            } else {
+                Ok(None)
+            }
+        }
+        EntryType::Link => {
+            let target = entry.link_name()?.unwrap_or_default();
+
+            // Hard links are resolved relative to the root of the archive
+            if target
+                .components()
+                .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+            {
+                Ok(None)
+            } else {
+                Ok(Some("hard link pointing outside of the destination"))
+            }
+        }
+        EntryType::Char | EntryType::Block => Ok(Some("device node")),
+        EntryType::Fifo => Ok(Some("named pipe")),
+        _ => Ok(Some("unsupported entry type")),
+    }
+}
+
+/// Checks if a symlink's target stays within the root directory once resolved
+fn resolves_inside(root: &Path, link: &Path, target: &Path) -> Result<bool> {
+    let mut resolved = match link.parent() {
+        Some(parent) => fs::canonicalize(parent)?,
+        None => return Ok(false),
+    };
+
+    for component in target.components() {
+        match component {
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return Ok(false);
+                }
+            }
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => (),
+            Component::Prefix(_) | Component::RootDir => return Ok(false),
+        }
+    }
+
+    // Follows any symlink chain the target goes through, if it already exists
+    match fs::canonicalize(link) {
+        Ok(canonical) => Ok(resolved.starts_with(root) && canonical.starts_with(root)),
+        Err(_) => Ok(resolved.starts_with(root)),
+    }
+}
@@ -17,34 +17,88 @@
  *
  */
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::Parser;
+use colored::Colorize;
 use std::path::PathBuf;
-use vento::{common::get_current_dir, item};
+use vento::{common::get_current_dir, item, progress, store, template};
 
 #[derive(Parser)]
 #[command(name = "Drop")]
 #[command(about = "A file dropper for Vento", long_about = None)]
 #[command(author, version)]
 struct Cli {
-    /// Pick a slot to drop the file from
+    /// Pick a slot to drop the file from. Files can also be given as a location such as active:docs/notes.md
     #[arg(short, long)]
     slot: Option<String>,
 
-    /// File to drop from inventory
-    file: String,
+    /// Drop the items taken into the slot most recently instead, last one first
+    #[arg(long, value_name = "COUNT", num_args = 0..=1, default_missing_value = "1")]
+    pop: Option<usize>,
+
+    /// Directory to drop popped items into
+    #[arg(long, value_name = "DIRECTORY", requires = "pop")]
+    into: Option<PathBuf>,
+
+    /// Replace {{project}}, {{date}} and {{author}} in a template as it's copied out
+    #[arg(long, conflicts_with = "pop")]
+    render: bool,
+
+    /// Report progress as a bar, as lines of JSON or not at all: auto, bar, json or quiet
+    #[arg(long, value_name = "MODE")]
+    progress: Option<String>,
+
+    /// File to drop from inventory, or the last one taken if none is given
+    #[arg(conflicts_with = "pop")]
+    file: Option<String>,
     /// Location to drop file onto
+    #[arg(conflicts_with = "pop")]
     output: Option<PathBuf>,
 }
 
 fn main() -> Result<()> {
     // Handles args in Drop
     let cli = Cli::parse();
+    if let Some(mode) = &cli.progress {
+        progress::set_mode(mode.parse()?);
+    }
     let unwrapped_slot = cli.slot.clone().unwrap_or(String::from("active"));
-    let slot = unwrapped_slot.as_str();
-    let out = cli.output.unwrap_or(get_current_dir()?);
+    let mut slot = unwrapped_slot.as_str();
+
+    // Items can also be given as a location such as active:docs/notes.md, which picks the slot too
+    let location = cli.file.clone();
+    let file = match location.as_deref().and_then(store::parse_location) {
+        Some((location_slot, path)) => {
+            slot = location_slot;
+            Some(String::from(path))
+        }
+        None => cli.file,
+    };
+    let display_slot = cli.slot.is_some() || file != location;
+
+    // Templates are copied out rather than moved, so they can be dropped again
+    if template::is_templates(slot) {
+        let Some(file) = file else {
+            bail!("{}", "Templates can only be dropped by name".red());
+        };
+        let out = cli.output.unwrap_or(get_current_dir()?);
+        return template::copy(&file, out, cli.render, true);
+    }
+    if cli.render {
+        bail!("{}", "Only templates can be rendered".red());
+    }
 
-    item::drop(&cli.file, slot, out, true, cli.slot.is_some(), true)?;
+    match (cli.pop, file) {
+        (Some(count), _) => {
+            let out = cli.into.unwrap_or(get_current_dir()?);
+            item::pop(count, slot, out, true, display_slot)?;
+        }
+        (None, Some(file)) => {
+            let out = cli.output.unwrap_or(get_current_dir()?);
+            item::drop(&file, slot, out, true, display_slot, true)?;
+        }
+        (None, None) => item::pop(1, slot, get_current_dir()?, true, display_slot)?,
+    }
 
     Ok(())
 }
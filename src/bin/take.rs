@@ -19,17 +19,45 @@
 
 use anyhow::Result;
 use clap::Parser;
-use vento::{common::override_color, item};
+use std::path::Path;
+use vento::{
+    common::{override_color, parse_duration},
+    item, meta, progress, store,
+};
 
 #[derive(Parser)]
 #[command(name = "Take")]
 #[command(about = "A file grabber for Vento", long_about = None)]
 #[command(author, version)]
 struct Cli {
-    /// Pick a slot to take the file into
+    /// Pick a slot to take the file into, or a directory inside of it such as active:docs
     #[arg(short, long)]
     slot: Option<String>,
 
+    /// Push the file onto the active slot, so drop --pop gives it back first
+    #[arg(long, conflicts_with = "slot")]
+    push: bool,
+
+    /// Leave a symlink where the file was, pointing to it inside the slot
+    #[arg(short, long)]
+    link: bool,
+
+    /// Tag the file, can be given more than once
+    #[arg(short, long)]
+    tag: Vec<String>,
+
+    /// Attach a note to the file
+    #[arg(short, long)]
+    note: Option<String>,
+
+    /// Expire the file after this long, such as 7d
+    #[arg(long, value_name = "AGE")]
+    ttl: Option<String>,
+
+    /// Report progress as a bar, as lines of JSON or not at all: auto, bar, json or quiet
+    #[arg(long, value_name = "MODE")]
+    progress: Option<String>,
+
     /// File to take
     file: String,
 }
@@ -38,8 +66,41 @@ fn main() -> Result<()> {
     // Handles args in Vento
     override_color()?;
     let cli = Cli::parse();
-    let slot = cli.slot.clone().unwrap_or(String::from("active"));
+    if let Some(mode) = &cli.progress {
+        progress::set_mode(mode.parse()?);
+    }
+    // Pushing is taking into the active slot, which drop pops from in the order items were taken
+    let slot = match cli.push {
+        true => String::from("active"),
+        false => cli.slot.clone().unwrap_or(String::from("active")),
+    };
+    let (slotname, dir) = store::parse_location(&slot).unwrap_or((&slot, ""));
+    let ttl = match cli.ttl {
+        Some(ttl) => Some(parse_duration(&ttl)?),
+        None => meta::default_ttl(slotname)?,
+    };
+
+    // Tags are checked before the file is taken, so an invalid one doesn't leave it taken without them
+    let mut tagged = meta::Metadata::default();
+    tagged.add_tags(&cli.tag)?;
+
+    item::take(&cli.file, &slot, true, cli.slot.is_some(), true, cli.link)?;
 
-    item::take(&cli.file, &slot, true, cli.slot.is_some(), true)?;
+    let name = Path::new(dir)
+        .join(Path::new(&cli.file).file_name().unwrap_or_default())
+        .to_string_lossy()
+        .to_string();
+    let slot = slotname;
+    if !cli.tag.is_empty() || cli.note.is_some() {
+        let mut metadata = meta::get(slot, &name)?.unwrap_or_default();
+        metadata.add_tags(&tagged.tags)?;
+        if cli.note.is_some() {
+            metadata.note = cli.note;
+        }
+        meta::set(slot, &name, &metadata)?;
+    }
+    if ttl.is_some() {
+        meta::expire(slot, &name, ttl)?;
+    }
     Ok(())
 }
@@ -22,9 +22,16 @@ use clap::Parser;
 use std::path::PathBuf;
 use vento::{
     archive,
-    common::override_color,
-    history, inv,
+    common::{get_current_dir, override_color, parse_duration, parse_size},
+    dedup, doctor,
+    find::{self, Query},
+    format::{Compression, Encryption, Format},
+    history,
+    inv::{self, ListOptions, Sort},
     message::{throw_error, ErrorType},
+    meta, progress,
+    snapshot::{self, Kind},
+    store, trash,
 };
 
 #[derive(Parser)]
@@ -35,6 +42,30 @@ struct Cli {
     #[arg(short, long)]
     slot: Option<String>,
 
+    /// List the items inside of directories too
+    #[arg(long)]
+    tree: bool,
+
+    /// How many levels deep to list, implying --tree
+    #[arg(long, value_name = "LEVELS")]
+    depth: Option<usize>,
+
+    /// Sort listed items by name, size, mtime or type
+    #[arg(long, value_name = "ORDER")]
+    sort: Option<String>,
+
+    /// List items in reverse order
+    #[arg(long)]
+    reverse: bool,
+
+    /// Show how long ago each item was taken, and where from
+    #[arg(long)]
+    origin: bool,
+
+    /// Flag items taken longer ago than this
+    #[arg(long, value_name = "AGE")]
+    stale_after: Option<String>,
+
     /// Switch slots
     #[arg(short = 'c', long)]
     switch: bool,
@@ -60,34 +91,183 @@ struct Cli {
     export_inv: Option<Vec<String>>,
 
     /// Export the Vento directory
-    #[arg(short = 'E', long, default_missing_value = "vento.tar.xz", value_name = "ARCHIVE", num_args = ..=1)]
-    export_dir: Option<PathBuf>,
+    #[arg(short = 'E', long, value_name = "ARCHIVE", num_args = ..=1)]
+    export_dir: Option<Option<PathBuf>>,
+
+    /// Only export what changed in the Vento directory since its last export
+    #[arg(long, requires = "export_dir", conflicts_with = "differential")]
+    incremental: bool,
+
+    /// Only export what changed in the Vento directory since its last full export
+    #[arg(long, requires = "export_dir")]
+    differential: bool,
+
+    /// Archive format to export into (tar.xz, tar.zst, tar.gz, tar or zip)
+    #[arg(short, long, value_name = "FORMAT")]
+    format: Option<String>,
+
+    /// Compression level to export with
+    #[arg(long, value_name = "LEVEL")]
+    level: Option<u32>,
+
+    /// Amount of threads to compress with
+    #[arg(long, value_name = "THREADS")]
+    threads: Option<u32>,
+
+    /// Encrypt the exported archive with a passphrase
+    #[arg(long)]
+    encrypt: bool,
+
+    /// Key file to encrypt an exported archive for, or to decrypt an archive with
+    #[arg(long, value_name = "FILE")]
+    key: Option<PathBuf>,
 
     /// Import an inventory archive
     #[arg(short = 'g', long, num_args = 1..=2, value_names = &["ARCHIVE", "SLOT"])]
     import_inv: Option<Vec<String>>,
 
+    /// Resolve name collisions when importing an inventory archive (skip, rename, overwrite or newer)
+    #[arg(long, value_name = "POLICY", requires = "import_inv")]
+    conflict: Option<String>,
+
+    /// Show what importing an inventory archive would do, without importing it
+    #[arg(long, requires = "import_inv")]
+    preview: bool,
+
+    /// Import a single item from an inventory archive
+    #[arg(long, value_name = "ITEM", requires = "import_inv")]
+    item: Option<String>,
+
+    /// List the items inside an archive
+    #[arg(long, value_name = "ARCHIVE")]
+    list_archive: Option<PathBuf>,
+
+    /// Verify an archive's contents against its manifest
+    #[arg(long, value_name = "ARCHIVE")]
+    verify: Option<PathBuf>,
+
+    /// Restore a chain of Vento directory archives into an empty directory
+    #[arg(long, value_names = &["DIRECTORY", "ARCHIVE"], num_args = 2..)]
+    restore: Option<Vec<PathBuf>>,
+
+    /// Save a snapshot of the Vento directory, pruning older ones
+    #[arg(long)]
+    snapshot: bool,
+
     /// Import a Vento directory archive
     #[arg(short = 'G', long, value_name = "ARCHIVE")]
     import_dir: Option<PathBuf>,
 
+    /// Find items across both slots, optionally by a name pattern
+    #[arg(long, value_name = "PATTERN", num_args = ..=1)]
+    find: Option<Option<String>>,
+
+    /// Match the pattern given to find as a regex, rather than a glob
+    #[arg(long, requires = "find")]
+    regex: bool,
+
+    /// Only find items with this extension
+    #[arg(long, value_name = "EXTENSION", requires = "find")]
+    ext: Option<String>,
+
+    /// Only find files at least this big
+    #[arg(long, value_name = "SIZE", requires = "find")]
+    min_size: Option<String>,
+
+    /// Only find files at most this big
+    #[arg(long, value_name = "SIZE", requires = "find")]
+    max_size: Option<String>,
+
+    /// Only find items modified within this long
+    #[arg(long, value_name = "AGE", requires = "find")]
+    newer: Option<String>,
+
+    /// Only find items modified at least this long ago
+    #[arg(long, value_name = "AGE", requires = "find")]
+    older: Option<String>,
+
+    /// Drop the items found into a directory, the current one by default
+    #[arg(long, value_name = "DESTINATION", num_args = ..=1, requires = "find")]
+    drop: Option<Option<PathBuf>>,
+
+    /// Check the Vento directory and its history for problems
+    #[arg(long)]
+    doctor: bool,
+
+    /// Fix the problems found while checking the Vento directory
+    #[arg(long, requires = "doctor")]
+    fix: bool,
+
+    /// Edit the tags and note of an item
+    #[arg(long, value_name = "ITEM")]
+    edit: Option<String>,
+
+    /// Add a tag to the item being edited, or only find items with it. Can be given more than once
+    #[arg(short, long)]
+    tag: Vec<String>,
+
+    /// Remove a tag from the item being edited, can be given more than once
+    #[arg(long, value_name = "TAG", requires = "edit")]
+    untag: Vec<String>,
+
+    /// Set the note of the item being edited, or clear it if empty
+    #[arg(short, long, requires = "edit")]
+    note: Option<String>,
+
+    /// Set how long the item being edited lasts before expiring, or never
+    #[arg(long, value_name = "AGE", requires = "edit")]
+    ttl: Option<String>,
+
+    /// Throw an item away into the trash
+    #[arg(long, value_name = "ITEM")]
+    discard: Option<String>,
+
+    /// Store every file in the Vento directory once, and remove objects nothing uses anymore
+    #[arg(long)]
+    dedup: bool,
+
+    /// Move expired items into the trash, and purge old items from it
+    #[arg(long)]
+    gc: bool,
+
     /// Initialize Vento
     #[arg(short, long)]
     init: bool,
 
+    /// Keep the history when reinitializing Vento
+    #[arg(long, requires = "init")]
+    keep_history: bool,
+
+    /// Keep every slot when reinitializing Vento
+    #[arg(long, requires = "init")]
+    keep_slots: bool,
+
+    /// Report progress as a bar, as lines of JSON or not at all: auto, bar, json or quiet
+    #[arg(long, value_name = "MODE")]
+    progress: Option<String>,
+
     directory: Option<String>,
 }
 
+#[allow(clippy::unnecessary_unwrap)]
 fn main() -> Result<()> {
     override_color()?;
     let cli = Cli::parse();
+    if let Some(mode) = &cli.progress {
+        progress::set_mode(mode.parse()?);
+    }
     let unwrapped_dir = cli.directory.unwrap_or(String::new());
     let dir = unwrapped_dir.as_str();
+    let encryption = match (&cli.key, cli.encrypt) {
+        (Some(key), _) => Some(Encryption::Key(key.clone())),
+        (None, true) => Some(Encryption::Passphrase),
+        (None, false) => None,
+    };
 
     if cli.switch {
         inv::switch(true, true)?
     } else if cli.init {
-        inv::init()?
+        inv::init(cli.keep_history, cli.keep_slots)?
     } else if cli.undo.is_some() {
         history::undo(cli.undo.unwrap_or(1))?
     } else if cli.redo.is_some() {
@@ -97,52 +277,154 @@ fn main() -> Result<()> {
     } else if cli.migrate {
         history::migrate()?;
     } else if cli.export_inv.is_some() {
-        let unwrapped_export_inv = cli.export_inv.unwrap();
-        let export_inv_values = match unwrapped_export_inv.len() {
-            0 => vec![String::from("active"), String::from("active.tar.xz")],
-            _ => unwrapped_export_inv,
+        let export_inv_values = cli.export_inv.unwrap();
+        let format = match cli.format {
+            Some(format) => Some(format.parse::<Format>()?),
+            None => None,
         };
-
-        archive::export_inv(
-            match export_inv_values[0].as_str() {
-                "active" | "inactive" | "a" | "i" => export_inv_values[0].as_str(),
+        let slot = match export_inv_values.first().map(|value| value.as_str()) {
+            Some("active" | "inactive" | "a" | "i") => export_inv_values[0].as_str(),
+            _ => "active",
+        };
+        let output = match export_inv_values.first().map(|value| value.as_str()) {
+            Some("active" | "inactive" | "a" | "i") => export_inv_values.get(1).cloned(),
+            _ => export_inv_values.first().cloned(),
+        };
+        let output = PathBuf::from(output.unwrap_or(format!(
+            "{}.{}",
+            match slot {
+                "inactive" | "i" => "inactive",
                 _ => "active",
             },
-            PathBuf::from(match export_inv_values[0].as_str() {
-                "active" | "inactive" | "a" | "i" => export_inv_values[1].as_str(),
-                _ => export_inv_values[0].as_str(),
-            }),
-            true,
-        )?
+            format.unwrap_or(Format::TarXz).extension()
+        )));
+
+        let compression = Compression::new(format, &output, cli.level, cli.threads, encryption)?;
+        archive::export_inv(slot, output, &compression, true)?
     } else if cli.export_dir.is_some() {
-        archive::export_dir(cli.export_dir.unwrap(), true)?
+        let export_dir = cli.export_dir.unwrap();
+        let format = match cli.format {
+            Some(format) => Some(format.parse::<Format>()?),
+            None => None,
+        };
+        let output = export_dir.unwrap_or(PathBuf::from(format!(
+            "vento.{}",
+            format.unwrap_or(Format::TarXz).extension()
+        )));
+
+        let compression = Compression::new(format, &output, cli.level, cli.threads, encryption)?;
+        let kind = match (cli.incremental, cli.differential) {
+            (true, _) => Kind::Incremental,
+            (_, true) => Kind::Differential,
+            _ => Kind::Full,
+        };
+        archive::export_dir(output, &compression, kind, true, true)?
     } else if cli.import_inv.is_some() {
         let import_inv_values = &cli
             .import_inv
             .unwrap_or(vec![String::new(), String::from("active")]);
 
+        let conflict = match cli.conflict {
+            Some(policy) => Some(policy.parse::<archive::Conflict>()?),
+            None => None,
+        };
+        let slot = match import_inv_values.len() {
+            2 => match import_inv_values[1].as_str() {
+                "active" | "inactive" | "a" | "i" => import_inv_values[1].as_str(),
+                _ => "active",
+            },
+            _ => "active",
+        };
+
         match import_inv_values[0].as_str() {
             "" | "active" | "inactive" | "a" | "i" => throw_error(ErrorType::SpecifyFile)?,
-            _ => archive::import_inv(
-                PathBuf::from(&import_inv_values[0]),
-                match import_inv_values.len() {
-                    2 => match import_inv_values[1].as_str() {
-                        "active" | "inactive" | "a" | "i" => import_inv_values[1].as_str(),
-                        _ => "active",
-                    },
-                    _ => "active",
-                },
-                true,
-            )?,
+            _ => match cli.preview {
+                true => archive::preview_inv(
+                    PathBuf::from(&import_inv_values[0]),
+                    slot,
+                    conflict,
+                    cli.item.as_deref(),
+                    cli.key.as_deref(),
+                )?,
+                false => archive::import_inv(
+                    PathBuf::from(&import_inv_values[0]),
+                    slot,
+                    conflict,
+                    cli.item.as_deref(),
+                    cli.key.as_deref(),
+                    true,
+                )?,
+            },
+        };
+    } else if let Some(list_archive) = cli.list_archive {
+        archive::list(list_archive, cli.key.as_deref())?
+    } else if let Some(verify) = cli.verify {
+        archive::verify(verify, cli.key.as_deref())?
+    } else if let Some(mut restore) = cli.restore {
+        let dest = restore.remove(0);
+        archive::restore(dest, restore, cli.key.as_deref(), true)?
+    } else if let Some(edit) = cli.edit {
+        meta::edit(
+            &edit,
+            cli.slot.as_deref().unwrap_or("active"),
+            &cli.tag,
+            &cli.untag,
+            cli.note,
+            match cli.ttl.as_deref() {
+                Some("never") => Some(None),
+                Some(ttl) => Some(Some(parse_duration(ttl)?)),
+                None => None,
+            },
+        )?
+    } else if let Some(pattern) = cli.find {
+        let query = Query {
+            pattern,
+            regex: cli.regex,
+            tags: cli.tag,
+            extension: cli.ext,
+            min_size: cli.min_size.as_deref().map(parse_size).transpose()?,
+            max_size: cli.max_size.as_deref().map(parse_size).transpose()?,
+            newer: cli.newer.as_deref().map(parse_duration).transpose()?,
+            older: cli.older.as_deref().map(parse_duration).transpose()?,
         };
+        match cli.drop {
+            Some(dest) => find::drop(&query, dest.unwrap_or(get_current_dir()?))?,
+            None => find::find(&query)?,
+        }
+    } else if cli.doctor {
+        doctor::doctor(cli.fix)?
+    } else if let Some(discard) = cli.discard {
+        trash::discard(&discard, cli.slot.as_deref().unwrap_or("active"), true)?
+    } else if cli.dedup {
+        dedup::dedup(true)?
+    } else if cli.gc {
+        trash::gc(true)?
+    } else if cli.snapshot {
+        snapshot::take(true)?
     } else if cli.import_dir.is_some() {
-        archive::import_dir(cli.import_dir.unwrap(), true)?
+        archive::import_dir(cli.import_dir.unwrap(), cli.key.as_deref(), true)?
     } else {
-        inv::list(
-            cli.slot.clone().unwrap_or(String::from("active")).as_str(),
-            dir,
-            cli.slot.is_some(),
-        )?
+        let options = ListOptions {
+            tree: cli.tree || cli.depth.is_some(),
+            depth: cli.depth,
+            sort: match cli.sort {
+                Some(sort) => sort.parse()?,
+                None => Sort::default(),
+            },
+            reverse: cli.reverse,
+            origin: cli.origin,
+            stale_after: cli.stale_after.as_deref().map(parse_duration).transpose()?,
+        };
+        // The directory can also be a location such as active:docs, which picks the slot too
+        match store::parse_location(dir) {
+            Some((slot, dir)) => inv::list(slot, dir, true, &options)?,
+            None => inv::list(
+                cli.slot.clone().unwrap_or(String::from("active")).as_str(),
+                dir,
+                cli.slot.is_some(),
+                &options,
+            )?,
+        }
     }
 
     Ok(())
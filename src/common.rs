@@ -18,8 +18,10 @@
  */
 
 use crate::message::{throw_error, ErrorType};
-use anyhow::Result;
+use crate::template;
+use anyhow::{bail, Result};
 use colored::control::set_override;
+use colored::Colorize;
 use config::Config;
 use rusqlite::Connection;
 use serde::Deserialize;
@@ -31,6 +33,7 @@ pub struct Settings {
     pub vento_dir: PathBuf,
     pub active_dir: PathBuf,
     pub inactive_dir: PathBuf,
+    pub templates_dir: PathBuf,
 }
 
 #[derive(Debug)]
@@ -42,6 +45,8 @@ pub struct HistoryData {
     pub action: Action,
     pub time: i64,
     pub current: i32,
+    /// Whether a symlink was left where a taken item used to be, or was replaced when the item was dropped back
+    pub link: bool,
 }
 
 pub struct DeserializedConfig {
@@ -50,6 +55,29 @@ pub struct DeserializedConfig {
     pub history_display_dir: bool,
     pub display_emoji: bool,
     pub display_colors: bool,
+    pub archive_max_size: u64,
+    pub archive_max_entries: u64,
+    pub archive_format: String,
+    pub archive_level: Option<u32>,
+    pub archive_threads: u32,
+    pub archive_encrypt: bool,
+    pub archive_key_file: Option<PathBuf>,
+    pub snapshot_dir: Option<PathBuf>,
+    pub snapshot_keep_last: usize,
+    pub snapshot_keep_daily: usize,
+    pub snapshot_keep_weekly: usize,
+    pub snapshot_auto: bool,
+    pub init_backup: String,
+    pub list_stale_after: Option<u64>,
+    pub ttl_active: Option<u64>,
+    pub ttl_inactive: Option<u64>,
+    pub gc_grace: u64,
+    pub trash_backend: String,
+    pub storage_dedup: bool,
+    pub compress_active: bool,
+    pub compress_inactive: bool,
+    pub compress_level: i32,
+    pub progress_mode: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -69,6 +97,7 @@ pub enum Action {
     Take,
     Drop,
     Switch,
+    Trash,
 }
 
 /// Provides required variables for Vento
@@ -94,20 +123,51 @@ pub fn env_config() -> Result<Settings> {
         .iter()
         .collect();
 
+    let templates_dir = vento_dir.join(template::TEMPLATES);
+
     Ok(Settings {
         vento_dir,
         active_dir,
         inactive_dir,
+        templates_dir,
     })
 }
 
 /// Handles reading the config file or variables for Vento.
+#[allow(clippy::manual_unwrap_or_default)]
 pub fn parse_config() -> Result<DeserializedConfig> {
     let mut directory = String::new();
     let mut display_dir = true;
     let mut history_display_dir = true;
     let mut display_emoji = true;
     let mut display_colors = true;
+    let mut archive_max_size: u64 = 64 << 30;
+    let mut archive_max_entries: u64 = 1_000_000;
+    let mut archive_format = String::from("tar.xz");
+    let mut archive_level: Option<u32> = None;
+    let mut archive_threads: u32 = 1;
+    let mut archive_encrypt = false;
+    let mut archive_key_file: Option<PathBuf> = None;
+    let mut snapshot_dir: Option<PathBuf> = None;
+    let mut snapshot_keep_last: usize = 5;
+    let mut snapshot_keep_daily: usize = 7;
+    let mut snapshot_keep_weekly: usize = 4;
+    let mut snapshot_auto = false;
+    let mut init_backup = String::from("move");
+    let mut list_stale_after: Option<u64> = None;
+    let mut ttl_active: Option<u64> = None;
+    let mut ttl_inactive: Option<u64> = None;
+    let mut gc_grace: u64 = 30 * 24 * 60 * 60;
+    // Only desktops following the freedesktop.org specifications have a trash Vento knows how to use
+    let mut trash_backend = String::from(match cfg!(all(unix, not(target_os = "macos"))) {
+        true => "xdg",
+        false => "internal",
+    });
+    let mut storage_dedup = false;
+    let mut compress_active = false;
+    let mut compress_inactive = false;
+    let mut compress_level: i32 = 3;
+    let mut progress_mode = String::from("auto");
     let mut config = match dirs::config_dir() {
         Option::Some(dir) => dir,
         _ => PathBuf::new(),
@@ -132,6 +192,89 @@ pub fn parse_config() -> Result<DeserializedConfig> {
             history_display_dir = settings.get_bool("history.display_dir").unwrap_or(true);
             display_emoji = settings.get_bool("display_emoji").unwrap_or(true);
             display_colors = settings.get_bool("display_colors").unwrap_or(true);
+            if let Ok(value) = settings.get_string("archive.max_size") {
+                archive_max_size = parse_size(&value)?;
+            }
+            if let Ok(value) = settings.get_int("archive.max_entries") {
+                archive_max_entries = match value.try_into() {
+                    Ok(value) => value,
+                    Err(_) => bail!("{}", "archive.max_entries can't be a negative number".red()),
+                };
+            }
+            if let Ok(value) = settings.get_string("archive.format") {
+                archive_format = value;
+            }
+            archive_level = settings
+                .get_int("archive.level")
+                .ok()
+                .and_then(|value| value.try_into().ok());
+            if let Ok(value) = settings.get_int("archive.threads") {
+                archive_threads = match value.try_into() {
+                    Ok(value) => value,
+                    Err(_) => bail!("{}", "archive.threads can't be a negative number".red()),
+                };
+            }
+            archive_encrypt = settings.get_bool("archive.encrypt").unwrap_or(false);
+            archive_key_file = settings
+                .get_string("archive.key_file")
+                .ok()
+                .map(PathBuf::from);
+            snapshot_dir = settings
+                .get_string("snapshot.directory")
+                .ok()
+                .map(PathBuf::from);
+            if let Ok(value) = settings.get_int("snapshot.keep_last") {
+                snapshot_keep_last = match value.try_into() {
+                    Ok(value) => value,
+                    Err(_) => bail!("{}", "snapshot.keep_last can't be a negative number".red()),
+                };
+            }
+            if let Ok(value) = settings.get_int("snapshot.keep_daily") {
+                snapshot_keep_daily = match value.try_into() {
+                    Ok(value) => value,
+                    Err(_) => bail!("{}", "snapshot.keep_daily can't be a negative number".red()),
+                };
+            }
+            if let Ok(value) = settings.get_int("snapshot.keep_weekly") {
+                snapshot_keep_weekly = match value.try_into() {
+                    Ok(value) => value,
+                    Err(_) => bail!(
+                        "{}",
+                        "snapshot.keep_weekly can't be a negative number".red()
+                    ),
+                };
+            }
+            snapshot_auto = settings.get_bool("snapshot.auto").unwrap_or(false);
+            if let Ok(value) = settings.get_string("init.backup") {
+                init_backup = value;
+            }
+            if let Ok(value) = settings.get_string("list.stale_after") {
+                list_stale_after = Some(parse_duration(&value)?);
+            }
+            if let Ok(value) = settings.get_string("ttl.active") {
+                ttl_active = Some(parse_duration(&value)?);
+            }
+            if let Ok(value) = settings.get_string("ttl.inactive") {
+                ttl_inactive = Some(parse_duration(&value)?);
+            }
+            if let Ok(value) = settings.get_string("gc.grace") {
+                gc_grace = parse_duration(&value)?;
+            }
+            if let Ok(value) = settings.get_string("trash.backend") {
+                trash_backend = value;
+            }
+            storage_dedup = settings.get_bool("storage.dedup").unwrap_or(false);
+            compress_active = settings.get_bool("compress.active").unwrap_or(false);
+            compress_inactive = settings.get_bool("compress.inactive").unwrap_or(false);
+            if let Ok(value) = settings.get_string("progress.mode") {
+                progress_mode = value;
+            }
+            if let Ok(value) = settings.get_int("compress.level") {
+                compress_level = match value {
+                    1..=22 => value as i32,
+                    _ => bail!("{}", "compress.level has to be between 1 and 22".red()),
+                };
+            }
         }
     };
 
@@ -141,27 +284,96 @@ pub fn parse_config() -> Result<DeserializedConfig> {
         history_display_dir,
         display_emoji,
         display_colors,
+        archive_max_size,
+        archive_max_entries,
+        archive_format,
+        archive_level,
+        archive_threads,
+        archive_encrypt,
+        archive_key_file,
+        snapshot_dir,
+        snapshot_keep_last,
+        snapshot_keep_daily,
+        snapshot_keep_weekly,
+        snapshot_auto,
+        init_backup,
+        list_stale_after,
+        ttl_active,
+        ttl_inactive,
+        gc_grace,
+        trash_backend,
+        storage_dedup,
+        compress_active,
+        compress_inactive,
+        compress_level,
+        progress_mode,
     })
 }
 
+/// Parses a size such as "512", "300K" or "16G" into bytes, using binary units
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let digits = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(digits);
+
+    let multiplier: u64 = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => bail!("{}", format!("Invalid size \"{}\"", size).red()),
+    };
+
+    match number.parse::<u64>() {
+        Ok(value) => Ok(value.saturating_mul(multiplier)),
+        Err(_) => bail!("{}", format!("Invalid size \"{}\"", size).red()),
+    }
+}
+
+/// Parses a duration such as "90", "15m", "2h", "7d" or "2w" into seconds, defaulting to seconds
+pub fn parse_duration(duration: &str) -> Result<u64> {
+    let duration = duration.trim();
+    let digits = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(digits);
+
+    let multiplier: u64 = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        _ => bail!("{}", format!("Invalid duration \"{}\"", duration).red()),
+    };
+
+    match number.parse::<u64>() {
+        Ok(value) => Ok(value.saturating_mul(multiplier)),
+        Err(_) => bail!("{}", format!("Invalid duration \"{}\"", duration).red()),
+    }
+}
+
+/// Formats an amount of seconds in the largest unit that fits it, the way durations are parsed
+pub fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        86400..604800 => format!("{}d", seconds / 86400),
+        _ => format!("{}w", seconds / 604800),
+    }
+}
+
 /// Writes an action into the history database
 pub fn history(data: HistoryData) -> Result<()> {
     let mut path = env_config()?.vento_dir;
     path.push("history.db3");
     let db = Connection::open(path)?;
 
-    // Create table if it doesn't exist.
-    db.execute(
-        "CREATE TABLE IF NOT EXISTS history (
-                id      INTEGER PRIMARY KEY,
-                path    TEXT,
-                file    TEXT,
-                slot    TEXT,
-                action  TEXT NOT NULL,
-		time	INTEGER NOT NULL,
-                current INTEGER NOT NULL)",
-        (),
-    )?;
+    history_table(&db)?;
 
     // Remove future actions
     let mut current = db.prepare("SELECT id FROM history WHERE current = 1")?;
@@ -174,7 +386,7 @@ pub fn history(data: HistoryData) -> Result<()> {
 
     // Insert action into table
     db.execute(
-        "INSERT INTO history (path, file, slot, action, time, current) VALUES (?1, ?2, ?3, ?4, ?5, 1)",
+        "INSERT INTO history (path, file, slot, action, time, current, link) VALUES (?1, ?2, ?3, ?4, ?5, 1, ?6)",
         (
             data.path.unwrap_or_default().to_str(),
             data.file,
@@ -183,14 +395,46 @@ pub fn history(data: HistoryData) -> Result<()> {
                 Action::Take => "take",
                 Action::Drop => "drop",
                 Action::Switch => "switch",
+                Action::Trash => "trash",
             },
             SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::new(0, 0)).as_secs(),
+            data.link,
         ),
     )?;
 
     Ok(())
 }
 
+/// Creates the history table if it doesn't exist
+pub fn history_table(db: &Connection) -> Result<()> {
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS history (
+                id      INTEGER PRIMARY KEY,
+                path    TEXT,
+                file    TEXT,
+                slot    TEXT,
+                action  TEXT NOT NULL,
+		time	INTEGER NOT NULL,
+                current INTEGER NOT NULL,
+                link    INTEGER NOT NULL DEFAULT 0)",
+        (),
+    )?;
+
+    // Links came after the table did, so older databases don't have them yet
+    let link: i64 = db.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('history') WHERE name = 'link'",
+        [],
+        |row| row.get(0),
+    )?;
+    if link == 0 {
+        db.execute(
+            "ALTER TABLE history ADD COLUMN link INTEGER NOT NULL DEFAULT 0",
+            (),
+        )?;
+    }
+    Ok(())
+}
+
 /// Gets current directory for commands
 pub fn get_current_dir() -> Result<PathBuf> {
     let currentdir = match current_dir() {
@@ -0,0 +1,253 @@
+/*
+ * Vento, a CLI inventory for your files.
+ * Copyright (C) 2023 Lux Aliaga
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::{
+    common, history,
+    message::{append_emoji, throw_error, EmojiType, ErrorType},
+    store, trash,
+};
+use anyhow::{bail, Result};
+use colored::Colorize;
+use rusqlite::Connection;
+#[cfg(unix)]
+use sha2::{Digest, Sha256};
+use size_format::SizeFormatterBinary;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+#[cfg(unix)]
+use std::{fs::File, io, os::unix::fs::MetadataExt};
+
+/// The name of the object store inside the Vento directory, where file contents are stored once under their hash and
+/// hardlinked into wherever they're used. The amount of links an object has is its reference count, so items in either
+/// slot, in the trash or in backups all keep it around, while snapshots are archives holding their own copies
+pub const OBJECTS: &str = ".objects";
+
+/// How much deduplicating some files saved
+#[derive(Default)]
+pub struct Savings {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Whether items are stored deduplicated, according to the config file.
+/// Objects are counted by how many hard links they have, so it's never the case outside of Unix
+pub fn enabled() -> Result<bool> {
+    Ok(cfg!(unix) && common::parse_config()?.storage_dedup)
+}
+
+/// Deduplicates everything in the Vento directory, then removes the objects nothing uses anymore
+pub fn dedup(message: bool) -> Result<()> {
+    let settings = common::env_config()?;
+    if !settings.vento_dir.is_dir() {
+        throw_error(ErrorType::NotInitialized)?;
+    }
+    if !cfg!(unix) {
+        bail!(
+            "{}",
+            "Deduplicating is only supported on Unix systems".red()
+        );
+    }
+
+    let mut savings = Savings::default();
+    let linked = linked()?;
+    for dir in [
+        settings.active_dir,
+        settings.inactive_dir,
+        settings.templates_dir,
+        settings.vento_dir.join(trash::TRASH),
+    ] {
+        if dir.is_dir() {
+            store_unlinked(&dir, &linked, &mut savings)?;
+        }
+    }
+    let collected = collect()?;
+
+    if message {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Success)?,
+            format!(
+                "Deduplicated {} files, saving {}B. Removed {} objects which weren't used anymore",
+                savings.files,
+                SizeFormatterBinary::new(savings.bytes),
+                collected
+            )
+            .green()
+        );
+    }
+    Ok(())
+}
+
+// Finds the items in the slots with a symlink left where they were taken from, according to the history
+fn linked() -> Result<BTreeSet<PathBuf>> {
+    let path = common::env_config()?.vento_dir.join("history.db3");
+    let mut linked = BTreeSet::new();
+    if !path.is_file() {
+        return Ok(linked);
+    }
+
+    for ((slot, file), placement) in history::replay(&Connection::open(path)?)? {
+        if let (true, Some(slotdir)) = (placement.link, store::open(&slot)?.path()) {
+            linked.insert(slotdir.join(file));
+        }
+    }
+    Ok(linked)
+}
+
+// Stores everything inside a directory, except for linked items. They're changed through their link outside of Vento,
+// which would change every file sharing their object too
+fn store_unlinked(path: &Path, linked: &BTreeSet<PathBuf>, savings: &mut Savings) -> Result<()> {
+    if linked.contains(path) {
+        return Ok(());
+    }
+    match path.is_dir() && !path.is_symlink() {
+        true => {
+            for item in fs::read_dir(path)? {
+                store_unlinked(&item?.path(), linked, savings)?;
+            }
+            Ok(())
+        }
+        false => store(path, savings),
+    }
+}
+
+/// Stores every file inside an item in the object store, linking copies of files already stored to their object
+#[cfg(unix)]
+pub fn store(path: &Path, savings: &mut Savings) -> Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for item in fs::read_dir(path)? {
+            store(&item?.path(), savings)?;
+        }
+        return Ok(());
+    }
+    if !metadata.is_file() || metadata.len() == 0 {
+        return Ok(());
+    }
+
+    let object = object_path(path)?;
+    match fs::symlink_metadata(&object) {
+        // Files are only shared with the same permissions, since links share them too
+        Ok(stored)
+            if stored.ino() != metadata.ino()
+                && stored.len() == metadata.len()
+                && stored.mode() == metadata.mode() =>
+        {
+            let temp = path.with_file_name(".vento-link");
+            if fs::hard_link(&object, &temp).is_ok() {
+                fs::rename(&temp, path)?;
+                savings.files += 1;
+                savings.bytes += metadata.len();
+            }
+        }
+        Ok(_) => (),
+        // Files in another filesystem can't be linked, so they just keep their own copy
+        Err(_) => {
+            fs::create_dir_all(object.parent().unwrap_or(&object))?;
+            let _ = fs::hard_link(path, &object);
+        }
+    }
+    Ok(())
+}
+
+/// Gives every file inside an item its own copy again, so changing it outside of Vento doesn't change the object it shares
+#[cfg(unix)]
+pub fn release(path: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for item in fs::read_dir(path)? {
+            release(&item?.path())?;
+        }
+        return Ok(());
+    }
+    if !metadata.is_file() || metadata.nlink() < 2 {
+        return Ok(());
+    }
+
+    let shared = fs::symlink_metadata(object_path(path)?)
+        .is_ok_and(|object| object.ino() == metadata.ino() && object.dev() == metadata.dev());
+    if shared {
+        let temp = path.with_file_name(".vento-copy");
+        fs::copy(path, &temp)?;
+        fs::rename(&temp, path)?;
+    }
+    Ok(())
+}
+
+/// Removes every object only the object store links to, returning how many were removed
+#[cfg(unix)]
+pub fn collect() -> Result<usize> {
+    let objects = common::env_config()?.vento_dir.join(OBJECTS);
+    if !objects.is_dir() {
+        return Ok(0);
+    }
+
+    let mut collected = 0;
+    for prefix in fs::read_dir(&objects)? {
+        let prefix = prefix?.path();
+        for object in fs::read_dir(&prefix)? {
+            let object = object?.path();
+            if fs::symlink_metadata(&object)?.nlink() < 2 {
+                fs::remove_file(&object)?;
+                collected += 1;
+            }
+        }
+        if fs::read_dir(&prefix)?.next().is_none() {
+            fs::remove_dir(&prefix)?;
+        }
+    }
+    Ok(collected)
+}
+
+// Outside of Unix there's no object store, so every file keeps its own copy
+#[cfg(not(unix))]
+pub fn store(_path: &Path, _savings: &mut Savings) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn release(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn collect() -> Result<usize> {
+    Ok(0)
+}
+
+// Finds where a file's contents are stored, by their hash
+#[cfg(unix)]
+fn object_path(path: &Path) -> Result<PathBuf> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    let hash: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    Ok(common::env_config()?
+        .vento_dir
+        .join(OBJECTS)
+        .join(&hash[..2])
+        .join(&hash[2..]))
+}
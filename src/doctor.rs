@@ -0,0 +1,395 @@
+/*
+ * Vento, a CLI inventory for your files.
+ * Copyright (C) 2023 Lux Aliaga
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::{
+    archive,
+    common::{self, Settings},
+    history,
+    message::{append_emoji, throw_error, EmojiType, ErrorType},
+    store,
+};
+use anyhow::Result;
+use colored::Colorize;
+use rusqlite::{Connection, ToSql};
+use std::{fs, path::Path};
+
+// Keeps count of the problems found, and whether they get fixed
+struct Report {
+    fix: bool,
+    found: usize,
+    fixed: usize,
+}
+
+impl Report {
+    // Prints a problem, returning whether it should be fixed
+    fn problem(&mut self, text: String, fixable: bool) -> Result<bool> {
+        let fixing = self.fix && fixable;
+        self.found += 1;
+        if fixing {
+            self.fixed += 1;
+        }
+
+        println!(
+            "{}{}{}",
+            append_emoji(EmojiType::Warning)?,
+            text.yellow(),
+            match (fixing, fixable) {
+                (true, _) => format!(" {}", "(fixed)".green()),
+                (false, false) => format!(" {}", "(can't be fixed automatically)".red()),
+                (false, true) => String::new(),
+            }
+        );
+        Ok(fixing)
+    }
+}
+
+/// Checks the Vento directory and its history for problems, fixing them if asked to
+pub fn doctor(fix: bool) -> Result<()> {
+    let settings = common::env_config()?;
+    if !settings.vento_dir.is_dir() {
+        throw_error(ErrorType::NotInitialized)?;
+    }
+
+    let mut report = Report {
+        fix,
+        found: 0,
+        fixed: 0,
+    };
+
+    check_switch(&settings, &mut report)?;
+    check_slots(&settings, &mut report)?;
+    check_staging(&settings, &mut report)?;
+    check_history(&settings, &mut report)?;
+
+    if report.found == 0 {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Success)?,
+            "No problems found in the Vento directory".green()
+        );
+    } else if fix {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Success)?,
+            format!(
+                "Fixed {} out of {} problems found",
+                report.fixed, report.found
+            )
+            .green()
+        );
+    } else {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Warning)?,
+            format!(
+                "Found {} problems. Run \"vento --doctor --fix\" to fix them",
+                report.found
+            )
+            .yellow()
+        );
+    }
+    Ok(())
+}
+
+/// Recovers from a switch that was interrupted halfway, by putting both slots back where they were
+fn check_switch(settings: &Settings, report: &mut Report) -> Result<()> {
+    let temp = settings.vento_dir.join("temp");
+    let (active, inactive) = (&settings.active_dir, &settings.inactive_dir);
+    if !temp.is_dir() {
+        return Ok(());
+    }
+
+    match (active.is_dir(), inactive.is_dir()) {
+        // The active slot was moved out of the way, but the inactive one wasn't moved into its place
+        (false, _) => {
+            if report.problem(
+                String::from("A switch was interrupted after moving the active slot away"),
+                true,
+            )? {
+                fs::rename(&temp, active)?;
+            }
+        }
+        // The inactive slot took the active one's place, but the active one wasn't moved into the inactive one's
+        (true, false) => {
+            if report.problem(
+                String::from("A switch was interrupted after moving the inactive slot"),
+                true,
+            )? {
+                fs::rename(active, inactive)?;
+                fs::rename(&temp, active)?;
+            }
+        }
+        (true, true) => match fs::read_dir(&temp)?.next().is_none() {
+            true => {
+                if report.problem(String::from("Leftover empty temp directory"), true)? {
+                    fs::remove_dir(&temp)?;
+                }
+            }
+            false => {
+                if report.problem(
+                    String::from("Leftover temp directory with files in it, next to both slots"),
+                    true,
+                )? {
+                    let backup = archive::backup_dir("doctor")?;
+                    fs::rename(&temp, backup.join("temp"))?;
+                    println!(
+                        "{}{} {}",
+                        append_emoji(EmojiType::Inventory)?,
+                        "Moved its contents into".green(),
+                        backup.join("temp").display()
+                    );
+                }
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Makes sure both slots exist
+fn check_slots(settings: &Settings, report: &mut Report) -> Result<()> {
+    for (slot, dir) in [
+        ("active", &settings.active_dir),
+        ("inactive", &settings.inactive_dir),
+    ] {
+        if dir.is_dir() {
+            continue;
+        }
+
+        match fs::symlink_metadata(dir).is_ok() {
+            true => {
+                report.problem(format!("The {} slot isn't a directory", slot), false)?;
+            }
+            false => {
+                if report.problem(format!("The {} slot is missing", slot), true)? {
+                    fs::create_dir_all(dir)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Cleans up after an import that was interrupted before its files were moved into place
+fn check_staging(settings: &Settings, report: &mut Report) -> Result<()> {
+    let staging = settings.vento_dir.join(".staging");
+
+    if staging.exists()
+        && report.problem(
+            String::from("Leftover staging directory from an interrupted import"),
+            true,
+        )?
+    {
+        fs::remove_dir_all(&staging)?;
+    }
+    Ok(())
+}
+
+/// Checks the history database's schema, its current action and whether it agrees with the slots
+fn check_history(settings: &Settings, report: &mut Report) -> Result<()> {
+    let path = settings.vento_dir.join("history.db3");
+    if !path.is_file() {
+        return Ok(());
+    }
+    let db = Connection::open(&path)?;
+
+    let tables: i64 = db.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'history'",
+        [],
+        |row| row.get(0),
+    )?;
+    if tables == 0 {
+        if report.problem(
+            String::from("The history database has no history table"),
+            true,
+        )? {
+            common::history_table(&db)?;
+        }
+        return Ok(());
+    }
+
+    let mut transaction = db.prepare("SELECT name FROM pragma_table_info('history')")?;
+    let columns: Vec<String> = transaction
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let missing: Vec<&str> = ["id", "path", "file", "slot", "action", "time", "current"]
+        .into_iter()
+        .filter(|column| !columns.iter().any(|found| found == column))
+        .collect();
+    if !missing.is_empty() {
+        // Nothing in the database can be trusted, so it's set aside for a new one to be started
+        if report.problem(
+            format!(
+                "The history table is missing the {} columns",
+                missing.join(", ")
+            ),
+            true,
+        )? {
+            drop(transaction);
+            drop(db);
+            let backup = archive::backup_dir("doctor")?;
+            fs::rename(&path, backup.join("history.db3"))?;
+            println!(
+                "{}{} {}",
+                append_emoji(EmojiType::Inventory)?,
+                "Moved the history database into".green(),
+                backup.join("history.db3").display()
+            );
+        }
+        return Ok(());
+    }
+
+    let invalid = "action NOT IN ('take', 'drop', 'switch', 'trash')
+        OR (action != 'switch' AND (file IS NULL OR slot IS NULL OR path IS NULL))";
+    let count: i64 = db.query_row(
+        &format!("SELECT COUNT(*) FROM history WHERE {}", invalid),
+        [],
+        |row| row.get(0),
+    )?;
+    if count > 0
+        && report.problem(
+            format!("{} actions in the history are invalid", count),
+            true,
+        )?
+    {
+        forget(&db, invalid, &[])?;
+    }
+
+    // Undoing and redoing step through the IDs one by one, so there can't be gaps between them
+    let (count, first, last): (i64, i64, i64) = db.query_row(
+        "SELECT COUNT(*), COALESCE(MIN(id), 1), COALESCE(MAX(id), 0) FROM history",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    if (first != 1 || last != count)
+        && report.problem(
+            String::from("The actions in the history aren't numbered one after another"),
+            true,
+        )?
+    {
+        compact(&db)?;
+    }
+
+    let current: i64 = db.query_row(
+        "SELECT COUNT(*) FROM history WHERE current = 1",
+        [],
+        |row| row.get(0),
+    )?;
+    if count > 0
+        && current != 1
+        && report.problem(
+            format!(
+                "The history should have exactly one current action, but it has {}",
+                current
+            ),
+            true,
+        )?
+    {
+        // Picks the latest action marked as current, or the latest one if none is
+        db.execute(
+            "UPDATE history SET current = CASE WHEN id = (
+                SELECT COALESCE(
+                    (SELECT MAX(id) FROM history WHERE current = 1),
+                    (SELECT MAX(id) FROM history)
+                )) THEN 1 ELSE 0 END",
+            (),
+        )?;
+    }
+
+    let placements = history::replay(&db)?;
+    let mut forgotten: Vec<String> = vec![];
+    for ((_, file), placement) in &placements {
+        let other = match placement.slot.as_str() {
+            "active" => "inactive",
+            _ => "active",
+        };
+        // Compressed items aren't stored under their own name, so the slots are asked instead of their directories
+        if store::open(&placement.slot)?.exists(Path::new(&file))? {
+            continue;
+        }
+
+        // An item by the same name in the other slot may be a different one the history knows about
+        let moved = !placements.contains_key(&(String::from(other), file.clone()))
+            && store::open(other)?.exists(Path::new(&file))?;
+        let text = match moved {
+            true => format!(
+                "The history says {} is in the {} slot, but it's in the other one",
+                file, placement.slot
+            ),
+            false => format!(
+                "The history says {} is in the {} slot, but it's missing",
+                file, placement.slot
+            ),
+        };
+        // Undoing actions on an item that isn't where it should be would fail, so they're forgotten,
+        // leaving alone the ones on items by the same name in other slots
+        if report.problem(text, true)? {
+            forgotten.extend(placement.actions.iter().map(i64::to_string));
+        }
+    }
+    // Forgetting numbers the actions again, so it's done once every item has been looked at
+    if !forgotten.is_empty() {
+        forget(&db, &format!("id IN ({})", forgotten.join(", ")), &[])?;
+    }
+
+    Ok(())
+}
+
+/// Removes the actions matching a condition from the history, keeping the current action where it was
+fn forget(db: &Connection, condition: &str, params: &[&dyn ToSql]) -> Result<()> {
+    let current: Option<i64> = db.query_row(
+        &format!(
+            "SELECT MAX(id) FROM history
+                WHERE id <= (SELECT COALESCE(MAX(id), 0) FROM history WHERE current = 1)
+                AND NOT ({})",
+            condition
+        ),
+        params,
+        |row| row.get(0),
+    )?;
+
+    db.execute(&format!("DELETE FROM history WHERE {}", condition), params)?;
+    db.execute("UPDATE history SET current = 0 WHERE current = 1", ())?;
+    match current {
+        Some(current) => {
+            db.execute("UPDATE history SET current = 1 WHERE id = ?1", [current])?;
+        }
+        // Nothing before the current action is left, so neither can what came after it
+        None => {
+            db.execute("DELETE FROM history", ())?;
+        }
+    }
+
+    compact(db)
+}
+
+/// Numbers the actions in the history one after another again
+fn compact(db: &Connection) -> Result<()> {
+    // Older databases don't have every column copied over yet
+    common::history_table(db)?;
+    db.execute_batch(
+        "BEGIN;
+        CREATE TEMP TABLE history_compact AS SELECT * FROM history ORDER BY id;
+        DELETE FROM history;
+        INSERT INTO history (path, file, slot, action, time, current, link)
+            SELECT path, file, slot, action, time, current, link FROM history_compact ORDER BY id;
+        DROP TABLE history_compact;
+        COMMIT;",
+    )?;
+    Ok(())
+}
@@ -0,0 +1,310 @@
+/*
+ * Vento, a CLI inventory for your files.
+ * Copyright (C) 2023 Lux Aliaga
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::{
+    common,
+    inv::{format_item, ItemKind},
+    item,
+    message::{append_emoji, throw_error, EmojiType, ErrorType},
+    meta::{self, Metadata},
+    store::{self, SlotStore},
+};
+use anyhow::{bail, Result};
+use colored::Colorize;
+use regex::Regex;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// What to search for across both slots. Every filter given has to match
+#[derive(Default)]
+pub struct Query {
+    pub pattern: Option<String>,
+    pub regex: bool,
+    pub tags: Vec<String>,
+    pub extension: Option<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub newer: Option<u64>,
+    pub older: Option<u64>,
+}
+
+/// An item found inside a slot
+pub struct Match {
+    pub slot: &'static str,
+    pub path: PathBuf,
+    pub kind: ItemKind,
+    pub size: Option<u64>,
+}
+
+// The name pattern, compiled once
+enum Pattern {
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Pattern::Glob(glob) => glob.matches(text),
+            Pattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+/// Searches both slots recursively, returning every match sorted by slot and path
+pub fn search(query: &Query) -> Result<Vec<Match>> {
+    let pattern = match &query.pattern {
+        Some(pattern) if query.regex => match Regex::new(pattern) {
+            Ok(regex) => Some(Pattern::Regex(regex)),
+            Err(_) => bail!("{}", format!("Invalid regex \"{}\"", pattern).red()),
+        },
+        Some(pattern) => match glob::Pattern::new(pattern) {
+            Ok(glob) => Some(Pattern::Glob(glob)),
+            Err(_) => bail!("{}", format!("Invalid pattern \"{}\"", pattern).red()),
+        },
+        None => None,
+    };
+
+    let mut matches = vec![];
+    for slot in ["active", "inactive"] {
+        let metadata = meta::slot(slot)?;
+        walk(
+            store::open(slot)?.as_ref(),
+            Path::new(""),
+            slot,
+            query,
+            pattern.as_ref(),
+            &metadata,
+            &mut matches,
+        )?;
+    }
+
+    Ok(matches)
+}
+
+/// Searches both slots, printing every match along with its slot and path inside of it
+pub fn find(query: &Query) -> Result<()> {
+    if !common::env_config()?.vento_dir.is_dir() {
+        throw_error(ErrorType::NotInitialized)?;
+    }
+    let matches = search(query)?;
+
+    if matches.is_empty() {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Inventory)?,
+            "No matches found".green()
+        );
+        return Ok(());
+    }
+
+    println!(
+        "{}{}",
+        append_emoji(EmojiType::Inventory)?,
+        format!(
+            "Found {} {}:",
+            matches.len().to_string().white().bold(),
+            match matches.len() {
+                1 => "match",
+                _ => "matches",
+            }
+        )
+        .green()
+    );
+    let metadata = [meta::slot("active")?, meta::slot("inactive")?];
+    for found in matches {
+        let name = found.path.to_string_lossy().to_string();
+        println!(
+            "{}{}",
+            format_item(
+                found.kind,
+                &format!(
+                    "{}:{}",
+                    match found.slot {
+                        "active" => found.slot.green(),
+                        _ => found.slot.blue(),
+                    },
+                    name
+                ),
+                found.size
+            ),
+            match metadata[(found.slot == "inactive") as usize].get(&name) {
+                Some(metadata) => metadata.describe(),
+                None => String::new(),
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Drops every item a search finds into a destination
+pub fn drop(query: &Query, dest: PathBuf) -> Result<()> {
+    if !common::env_config()?.vento_dir.is_dir() {
+        throw_error(ErrorType::NotInitialized)?;
+    }
+    let matches = search(query)?;
+
+    if matches.is_empty() {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Inventory)?,
+            "No matches found, so nothing was dropped".green()
+        );
+        return Ok(());
+    }
+
+    // Only whole items can be dropped, so matches inside of them are left where they are
+    let (items, nested): (Vec<Match>, Vec<Match>) = matches
+        .into_iter()
+        .partition(|found| found.path.components().count() == 1);
+    for found in &nested {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Warning)?,
+            format!(
+                "Skipped {}:{}, since it's inside another item",
+                found.slot,
+                found.path.display()
+            )
+            .yellow()
+        );
+    }
+
+    for found in items {
+        item::drop(
+            &found.path.to_string_lossy().to_string(),
+            found.slot,
+            dest.clone(),
+            true,
+            true,
+            true,
+        )?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn walk(
+    store: &dyn SlotStore,
+    prefix: &Path,
+    slot: &'static str,
+    query: &Query,
+    pattern: Option<&Pattern>,
+    metadata: &BTreeMap<String, Metadata>,
+    matches: &mut Vec<Match>,
+) -> Result<()> {
+    let mut items = store.list(prefix)?;
+    items.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for item in items {
+        let path = prefix.join(&item.name);
+        let size = match item.kind {
+            ItemKind::File => Some(item.size),
+            _ => None,
+        };
+
+        if matches_query(query, pattern, &path, size, item.modified, metadata) {
+            matches.push(Match {
+                slot,
+                path: path.clone(),
+                kind: item.kind,
+                size,
+            });
+        }
+
+        if matches!(item.kind, ItemKind::Directory) {
+            walk(store, &path, slot, query, pattern, metadata, matches)?;
+        }
+    }
+    Ok(())
+}
+
+fn matches_query(
+    query: &Query,
+    pattern: Option<&Pattern>,
+    path: &Path,
+    size: Option<u64>,
+    modified: Option<SystemTime>,
+    metadata: &BTreeMap<String, Metadata>,
+) -> bool {
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+
+    // Patterns with a slash in them are matched against the whole path, rather than the name
+    if let (Some(pattern), Some(text)) = (pattern, &query.pattern) {
+        let target = match text.contains('/') {
+            true => path.to_string_lossy().to_string(),
+            false => name.clone(),
+        };
+        if !pattern.matches(&target) {
+            return false;
+        }
+    }
+
+    // Tags belong to the item they were given to, and apply to everything inside of it
+    if !query.tags.is_empty() {
+        let tags: Vec<&String> = path
+            .ancestors()
+            .filter_map(|item| metadata.get(item.to_string_lossy().as_ref()))
+            .flat_map(|metadata| &metadata.tags)
+            .collect();
+        if !query.tags.iter().all(|tag| tags.contains(&tag)) {
+            return false;
+        }
+    }
+
+    if let Some(extension) = &query.extension {
+        let found = path
+            .extension()
+            .map(|found| found.to_string_lossy().to_lowercase());
+        if found.as_deref() != Some(extension.trim_start_matches('.').to_lowercase().as_str()) {
+            return false;
+        }
+    }
+
+    // Sizes only make sense for files
+    if query.min_size.is_some() || query.max_size.is_some() {
+        match size {
+            Some(size)
+                if query.min_size.is_none_or(|min| size >= min)
+                    && query.max_size.is_none_or(|max| size <= max) => {}
+            _ => return false,
+        }
+    }
+
+    if query.newer.is_some() || query.older.is_some() {
+        let age = modified
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or(Duration::ZERO)
+            .as_secs();
+        if query.newer.is_some_and(|newer| age > newer)
+            || query.older.is_some_and(|older| age < older)
+        {
+            return false;
+        }
+    }
+
+    true
+}
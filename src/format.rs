@@ -0,0 +1,753 @@
+/*
+ * Vento, a CLI inventory for your files.
+ * Copyright (C) 2023 Lux Aliaga
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::{
+    common,
+    message::{throw_error, ErrorType},
+};
+use age::{
+    secrecy::SecretString, stream::StreamWriter, x25519, Decryptor, Encryptor, IdentityFile,
+    Recipient,
+};
+use anyhow::{bail, Context, Result};
+use chrono::{Local, NaiveDateTime, TimeZone};
+use colored::Colorize;
+use flate2::{read::GzDecoder, write::GzEncoder};
+use rand::{distributions::Alphanumeric, Rng};
+use std::{
+    env,
+    fs::{self, File, Metadata, OpenOptions},
+    io::{self, BufWriter, Cursor, Read, Seek, SeekFrom, StdoutLock, Write},
+    iter,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::UNIX_EPOCH,
+};
+use tar::{Archive, EntryType, Header};
+use termion::input::TermRead;
+use xz2::{read::XzDecoder, stream::MtStreamBuilder, write::XzEncoder};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+/// Archive formats Vento can export to and import from
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    TarXz,
+    TarZst,
+    TarGz,
+    Tar,
+    Zip,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> Result<Self> {
+        match format.trim_start_matches('.') {
+            "tar.xz" | "txz" | "xz" => Ok(Format::TarXz),
+            "tar.zst" | "tzst" | "zst" | "zstd" => Ok(Format::TarZst),
+            "tar.gz" | "tgz" | "gz" | "gzip" => Ok(Format::TarGz),
+            "tar" => Ok(Format::Tar),
+            "zip" => Ok(Format::Zip),
+            _ => bail!(
+                "{}",
+                format!(
+                    "No such archive format. Valid formats are {}, {}, {}, {} and {}",
+                    "tar.xz".bold(),
+                    "tar.zst".bold(),
+                    "tar.gz".bold(),
+                    "tar".bold(),
+                    "zip".bold()
+                )
+                .red()
+            ),
+        }
+    }
+}
+
+impl Format {
+    /// Guesses the format of an archive from its file name
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+
+        [
+            (".tar.xz", Format::TarXz),
+            (".txz", Format::TarXz),
+            (".tar.zst", Format::TarZst),
+            (".tzst", Format::TarZst),
+            (".tar.gz", Format::TarGz),
+            (".tgz", Format::TarGz),
+            (".tar", Format::Tar),
+            (".zip", Format::Zip),
+        ]
+        .into_iter()
+        .find(|(extension, _)| name.ends_with(extension))
+        .map(|(_, format)| format)
+    }
+
+    /// Detects the format of an archive from the first bytes in it
+    pub fn from_magic(head: &[u8]) -> Format {
+        if head.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Format::TarXz
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Format::TarZst
+        } else if head.starts_with(&[0x1f, 0x8b]) {
+            Format::TarGz
+        } else if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+            Format::Zip
+        } else {
+            Format::Tar
+        }
+    }
+
+    /// The file extension archives in this format usually have
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::TarXz => "tar.xz",
+            Format::TarZst => "tar.zst",
+            Format::TarGz => "tar.gz",
+            Format::Tar => "tar",
+            Format::Zip => "zip",
+        }
+    }
+
+    // Compression levels each format supports, along with the one used by default
+    fn levels(&self) -> (u32, u32, u32) {
+        match self {
+            Format::TarXz => (0, 9, 9),
+            Format::TarZst => (1, 22, 3),
+            Format::TarGz | Format::Zip => (0, 9, 6),
+            Format::Tar => (0, 0, 0),
+        }
+    }
+}
+
+/// How an archive gets encrypted when it's written
+#[derive(Clone)]
+pub enum Encryption {
+    Passphrase,
+    Key(PathBuf),
+}
+
+/// Settings used when writing an archive
+#[derive(Clone)]
+pub struct Compression {
+    pub format: Format,
+    pub level: u32,
+    pub threads: u32,
+    pub encryption: Option<Encryption>,
+}
+
+impl Compression {
+    /// Picks the format from the flag given, the output's extension or the config file, in that order
+    pub fn new(
+        format: Option<Format>,
+        output: &Path,
+        level: Option<u32>,
+        threads: Option<u32>,
+        encryption: Option<Encryption>,
+    ) -> Result<Compression> {
+        let config = common::parse_config()?;
+
+        let format = match format.or(Format::from_path(output)) {
+            Some(format) => format,
+            None => config.archive_format.parse()?,
+        };
+
+        let (min, max, default) = format.levels();
+        let level = level.or(config.archive_level).unwrap_or(default);
+        if format != Format::Tar && (level < min || level > max) {
+            bail!(
+                "{}",
+                format!(
+                    "Invalid compression level for {}. Valid levels are {} to {}",
+                    format.extension(),
+                    min,
+                    max
+                )
+                .red()
+            );
+        }
+
+        let encryption = match (encryption, config.archive_encrypt) {
+            (Some(encryption), _) => Some(encryption),
+            (None, true) => Some(match config.archive_key_file {
+                Some(key) => Encryption::Key(key),
+                None => Encryption::Passphrase,
+            }),
+            (None, false) => None,
+        };
+
+        Ok(Compression {
+            format,
+            level,
+            threads: threads.unwrap_or(config.archive_threads).max(1),
+            encryption,
+        })
+    }
+}
+
+/// Compresses the tar stream being written into an archive
+pub enum Encoder<W: Write> {
+    Xz(XzEncoder<W>),
+    Zst(zstd::Encoder<'static, W>),
+    Gz(GzEncoder<W>),
+    Plain(W),
+}
+
+impl<W: Write> Encoder<W> {
+    fn new(compression: &Compression, inner: W) -> Result<Encoder<W>> {
+        Ok(match compression.format {
+            Format::TarXz => {
+                let stream = MtStreamBuilder::new()
+                    .preset(compression.level)
+                    .threads(compression.threads)
+                    .encoder()?;
+                Encoder::Xz(XzEncoder::new_stream(inner, stream))
+            }
+            Format::TarZst => {
+                let mut encoder = zstd::Encoder::new(inner, compression.level as i32)?;
+                if compression.threads > 1 {
+                    encoder.multithread(compression.threads)?;
+                }
+                Encoder::Zst(encoder)
+            }
+            Format::TarGz => Encoder::Gz(GzEncoder::new(
+                inner,
+                flate2::Compression::new(compression.level),
+            )),
+            Format::Tar | Format::Zip => Encoder::Plain(inner),
+        })
+    }
+
+    fn finish(self) -> Result<W> {
+        Ok(match self {
+            Encoder::Xz(encoder) => encoder.finish()?,
+            Encoder::Zst(encoder) => encoder.finish()?,
+            Encoder::Gz(encoder) => encoder.finish()?,
+            Encoder::Plain(inner) => inner,
+        })
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Xz(encoder) => encoder.write(buf),
+            Encoder::Zst(encoder) => encoder.write(buf),
+            Encoder::Gz(encoder) => encoder.write(buf),
+            Encoder::Plain(inner) => inner.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Xz(encoder) => encoder.flush(),
+            Encoder::Zst(encoder) => encoder.flush(),
+            Encoder::Gz(encoder) => encoder.flush(),
+            Encoder::Plain(inner) => inner.flush(),
+        }
+    }
+}
+
+/// Checks if a path stands for the standard input or output, rather than a file
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Where an archive gets written into
+pub enum Sink {
+    File(File),
+    Stdout(BufWriter<StdoutLock<'static>>),
+    Encrypted(Box<StreamWriter<Sink>>),
+}
+
+impl Sink {
+    /// Creates a file at the output path, or writes into the standard output if it's "-"
+    pub fn create(output: &Path, encryption: Option<&Encryption>) -> Result<Sink> {
+        if is_stdio(output) && termion::is_tty(&io::stdout()) {
+            bail!(
+                "{}",
+                "Refusing to write an archive into a terminal. Redirect the output somewhere else"
+                    .red()
+            );
+        }
+
+        // The key is read before the output is created, so a mistake doesn't leave an empty file behind
+        let encryptor = match encryption {
+            Some(Encryption::Passphrase) => {
+                Some(Encryptor::with_user_passphrase(passphrase(true)?))
+            }
+            Some(Encryption::Key(key)) => {
+                let recipients = recipients(key)?;
+                Some(Encryptor::with_recipients(
+                    recipients
+                        .iter()
+                        .map(|recipient| recipient.as_ref() as &dyn Recipient),
+                )?)
+            }
+            None => None,
+        };
+
+        let sink = match is_stdio(output) {
+            true => Sink::Stdout(BufWriter::new(io::stdout().lock())),
+            false => Sink::File(File::create(output)?),
+        };
+
+        Ok(match encryptor {
+            Some(encryptor) => Sink::Encrypted(Box::new(encryptor.wrap_output(sink)?)),
+            None => sink,
+        })
+    }
+
+    fn close(self) -> Result<()> {
+        match self {
+            Sink::File(file) => file.sync_all()?,
+            Sink::Stdout(mut stdout) => stdout.flush()?,
+            Sink::Encrypted(writer) => writer.finish()?.close()?,
+        }
+        Ok(())
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::File(file) => file.write(buf),
+            Sink::Stdout(stdout) => stdout.write(buf),
+            Sink::Encrypted(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::File(file) => file.flush(),
+            Sink::Stdout(stdout) => stdout.flush(),
+            Sink::Encrypted(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Writes entries into an archive of any supported format
+pub enum Writer {
+    Tar(Box<tar::Builder<Encoder<Sink>>>),
+    Zip(Box<ZipWriter<File>>, i64),
+}
+
+impl Writer {
+    /// Creates an archive at the output path, streaming it as it's written
+    pub fn create(output: &Path, compression: &Compression) -> Result<Writer> {
+        if compression.format == Format::Zip && compression.encryption.is_some() {
+            bail!(
+                "{}",
+                "Zip archives can't be encrypted. Try a tar format instead".red()
+            );
+        }
+
+        let sink = Sink::create(output, compression.encryption.as_ref())?;
+
+        Ok(match (compression.format, sink) {
+            (Format::Zip, Sink::File(file)) => {
+                Writer::Zip(Box::new(ZipWriter::new(file)), compression.level as i64)
+            }
+            // Zip archives keep their index at the end, so they can't be written as a stream
+            (Format::Zip, _) => bail!(
+                "{}",
+                "Zip archives can't be written into the standard output. Try a tar format instead"
+                    .red()
+            ),
+            (_, sink) => Writer::Tar(Box::new(tar::Builder::new(Encoder::new(
+                compression,
+                sink,
+            )?))),
+        })
+    }
+
+    /// Adds a directory, using the metadata from the one it comes from
+    pub fn append_dir(&mut self, path: &Path, metadata: &Metadata) -> Result<()> {
+        match self {
+            Writer::Tar(builder) => {
+                let mut header = Header::new_gnu();
+                header.set_metadata(metadata);
+                builder.append_data(&mut header, path, io::empty())?;
+            }
+            Writer::Zip(zip, level) => {
+                zip.add_directory(entry_name(path), zip_options(metadata, *level))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a file, reading its contents from a reader
+    pub fn append_file<R: Read>(
+        &mut self,
+        path: &Path,
+        metadata: &Metadata,
+        mut contents: R,
+    ) -> Result<()> {
+        match self {
+            Writer::Tar(builder) => {
+                let mut header = Header::new_gnu();
+                header.set_metadata(metadata);
+                builder.append_data(&mut header, path, contents)?;
+            }
+            Writer::Zip(zip, level) => {
+                zip.start_file(entry_name(path), zip_options(metadata, *level))?;
+                io::copy(&mut contents, zip)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a symlink pointing to a target, without following it
+    pub fn append_symlink(
+        &mut self,
+        path: &Path,
+        metadata: &Metadata,
+        target: &Path,
+    ) -> Result<()> {
+        match self {
+            Writer::Tar(builder) => {
+                let mut header = Header::new_gnu();
+                header.set_metadata(metadata);
+                header.set_entry_type(EntryType::Symlink);
+                header.set_size(0);
+                builder.append_link(&mut header, path, target)?;
+            }
+            Writer::Zip(zip, level) => {
+                zip.add_symlink(
+                    entry_name(path),
+                    target.to_string_lossy(),
+                    zip_options(metadata, *level),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a file with the given contents, as if it was just created
+    pub fn append_data(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        let now = Local::now();
+
+        match self {
+            Writer::Tar(builder) => {
+                let mut header = Header::new_gnu();
+                header.set_entry_type(EntryType::Regular);
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(now.timestamp().max(0) as u64);
+                builder.append_data(&mut header, path, data)?;
+            }
+            Writer::Zip(zip, level) => {
+                let mut options = SimpleFileOptions::default()
+                    .compression_method(CompressionMethod::Deflated)
+                    .compression_level(Some(*level))
+                    .unix_permissions(0o644);
+                if let Ok(time) = zip::DateTime::try_from(now.naive_local()) {
+                    options = options.last_modified_time(time);
+                }
+                zip.start_file(entry_name(path), options)?;
+                zip.write_all(data)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes writing the archive, flushing anything left
+    pub fn finish(self) -> Result<()> {
+        match self {
+            Writer::Tar(builder) => builder.into_inner()?.finish()?.close()?,
+            Writer::Zip(zip, _) => zip.finish()?.sync_all()?,
+        }
+        Ok(())
+    }
+}
+
+/// An opened archive, ready to have its entries read
+pub enum Reader {
+    Tar(Box<Archive<Box<dyn Read>>>),
+    Zip(Box<ZipArchive<Spool>>),
+}
+
+impl Reader {
+    /// Opens an archive, or reads it from the standard input if it's "-", detecting its format from its contents.
+    /// Encrypted archives are decrypted with the key file given, the one in the config file or a passphrase
+    pub fn open(input: &Path, key: Option<&Path>) -> Result<Reader> {
+        let mut source: Box<dyn Read> = match is_stdio(input) {
+            true => Box::new(io::stdin().lock()),
+            false => Box::new(File::open(input)?),
+        };
+
+        let mut head = peek(&mut source, AGE_MAGIC.len())?;
+        let encrypted = head.starts_with(AGE_MAGIC);
+        if encrypted {
+            source = decrypt(Box::new(Cursor::new(head).chain(source)), key)?;
+            head = peek(&mut source, 6)?;
+        }
+
+        // Just enough has been read to find out what the archive is compressed with
+        let format = Format::from_magic(&head);
+        let stream = Cursor::new(head).chain(source);
+
+        if format == Format::Zip {
+            let spool = match is_stdio(input) || encrypted {
+                true => Spool::from_stream(stream)?,
+                false => Spool::File(File::open(input)?),
+            };
+            return Ok(Reader::Zip(Box::new(ZipArchive::new(spool)?)));
+        }
+
+        let decoder: Box<dyn Read> = match format {
+            Format::TarXz => Box::new(XzDecoder::new_multi_decoder(stream)),
+            Format::TarZst => Box::new(zstd::Decoder::new(stream)?),
+            Format::TarGz => Box::new(GzDecoder::new(stream)),
+            _ => Box::new(stream),
+        };
+
+        Ok(Reader::Tar(Box::new(Archive::new(decoder))))
+    }
+}
+
+// Every age encrypted file starts with its version line
+const AGE_MAGIC: &[u8] = b"age-encryption.org/v1";
+
+fn peek(source: &mut Box<dyn Read>, length: usize) -> Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(length);
+    source.take(length as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+fn decrypt(source: Box<dyn Read>, key: Option<&Path>) -> Result<Box<dyn Read>> {
+    let decryptor = Decryptor::new(source)
+        .context("Vento was unable to read the archive's encryption header".red())?;
+
+    let stream = match decryptor.is_scrypt() {
+        true => {
+            let identity = age::scrypt::Identity::new(passphrase(false)?);
+            decryptor.decrypt(iter::once(&identity as &dyn age::Identity))
+        }
+        false => {
+            let key = match key {
+                Some(key) => key.to_path_buf(),
+                None => match common::parse_config()?.archive_key_file {
+                    Some(key) => key,
+                    None => bail!(
+                        "{}",
+                        format!(
+                            "This archive is encrypted with a key. Pass it with {}",
+                            "--key".bold()
+                        )
+                        .red()
+                    ),
+                },
+            };
+            let identities = IdentityFile::from_file(key.to_string_lossy().to_string())
+                .context(format!("Vento was unable to read the key file {}", key.display()).red())?
+                .into_identities()?;
+            decryptor.decrypt(identities.iter().map(|identity| identity.as_ref()))
+        }
+    };
+
+    match stream {
+        Ok(stream) => Ok(Box::new(stream)),
+        Err(age::DecryptError::DecryptionFailed | age::DecryptError::NoMatchingKeys) => bail!(
+            "{}",
+            "Vento was unable to decrypt the archive. The passphrase or key is wrong".red()
+        ),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Reads the passphrase from the VENTO_PASSPHRASE variable, or asks for it in the terminal
+fn passphrase(confirm: bool) -> Result<SecretString> {
+    if let Ok(passphrase) = env::var("VENTO_PASSPHRASE") {
+        return Ok(SecretString::from(passphrase));
+    }
+
+    // The terminal is used directly, since the archive itself might be coming through the standard input
+    let mut tty = termion::get_tty().context(
+        "No terminal to ask for a passphrase in. Set it through VENTO_PASSPHRASE instead".red(),
+    )?;
+    let mut ask = |prompt: &str| -> Result<String> {
+        write!(tty, "{}", prompt)?;
+        tty.flush()?;
+        let passphrase = tty.try_clone()?.read_passwd(&mut tty)?.unwrap_or_default();
+        writeln!(tty)?;
+        Ok(passphrase)
+    };
+
+    let passphrase = ask("Passphrase: ")?;
+    if passphrase.is_empty() {
+        bail!("{}", "The passphrase can't be empty".red());
+    }
+    if confirm && ask("Confirm passphrase: ")? != passphrase {
+        bail!("{}", "The passphrases don't match".red());
+    }
+
+    Ok(SecretString::from(passphrase))
+}
+
+// Key files may either be age identities, or a list of public keys to encrypt for
+fn recipients(key: &Path) -> Result<Vec<Box<dyn Recipient + Send>>> {
+    let contents = fs::read_to_string(key)
+        .context(format!("Vento was unable to read the key file {}", key.display()).red())?;
+    let lines: Vec<&str> = contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+
+    if !lines.is_empty() && lines.iter().all(|line| line.starts_with("age1")) {
+        return lines
+            .into_iter()
+            .map(|line| match line.parse::<x25519::Recipient>() {
+                Ok(recipient) => Ok(Box::new(recipient) as Box<dyn Recipient + Send>),
+                Err(_) => bail!(
+                    "{}",
+                    format!("Invalid public key in {}", key.display()).red()
+                ),
+            })
+            .collect();
+    }
+
+    Ok(IdentityFile::from_buffer(contents.as_bytes())
+        .context(format!("Vento was unable to read the key file {}", key.display()).red())?
+        .to_recipients()?)
+}
+
+/// The name of the directory inside the Vento directory which zip archives are spooled into
+pub const SPOOL: &str = ".spool";
+
+/// A zip archive being read, which needs to be seekable
+pub enum Spool {
+    File(File),
+    Temp(File, PathBuf),
+}
+
+impl Spool {
+    // Zip archives can't be read as a stream, so they're copied into a temporary file first.
+    // It's named randomly and never opened if it already exists, so nothing can be put in its place beforehand
+    fn from_stream<R: Read>(mut stream: R) -> Result<Spool> {
+        let vento_dir = common::env_config()?.vento_dir;
+        if !vento_dir.is_dir() {
+            throw_error(ErrorType::NotInitialized)?;
+        }
+        let dir = vento_dir.join(SPOOL);
+        fs::create_dir_all(&dir)?;
+
+        let (mut file, path) = loop {
+            let name: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(16)
+                .map(char::from)
+                .collect();
+            let path = dir.join(format!("{}.zip", name));
+
+            // Decrypted archives end up here, so only the user can read them
+            let mut options = OpenOptions::new();
+            options.read(true).write(true).create_new(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+
+            match options.open(&path) {
+                Ok(file) => break (file, path),
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(error) => return Err(error.into()),
+            }
+        };
+
+        // A stream failing midway, such as one with a wrong key, doesn't leave what was copied behind
+        let copied = io::copy(&mut stream, &mut file).and_then(|_| file.seek(SeekFrom::Start(0)));
+        if let Err(error) = copied {
+            let _ = fs::remove_file(&path);
+            return Err(error.into());
+        }
+        Ok(Spool::Temp(file, path))
+    }
+}
+
+impl Read for Spool {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Spool::File(file) | Spool::Temp(file, _) => file.read(buf),
+        }
+    }
+}
+
+impl Seek for Spool {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Spool::File(file) | Spool::Temp(file, _) => file.seek(pos),
+        }
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        if let Spool::Temp(_, path) = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Converts a timestamp stored in a zip archive into seconds since the epoch
+pub fn zip_time(time: Option<zip::DateTime>) -> u64 {
+    time.and_then(|time| NaiveDateTime::try_from(time).ok())
+        .and_then(|time| Local.from_local_datetime(&time).earliest())
+        .map(|time| time.timestamp().max(0) as u64)
+        .unwrap_or(0)
+}
+
+/// Names an entry inside an archive, always separating its path with forward slashes
+pub fn entry_name(path: &Path) -> String {
+    let name: Vec<String> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    name.join("/")
+}
+
+fn zip_options(metadata: &Metadata, level: i64) -> SimpleFileOptions {
+    let mut options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .compression_level(Some(level))
+        .large_file(metadata.len() >= u32::MAX as u64);
+
+    if let Ok(modified) = metadata.modified() {
+        let seconds = modified
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if let Some(time) = Local.timestamp_opt(seconds as i64, 0).single() {
+            if let Ok(time) = zip::DateTime::try_from(time.naive_local()) {
+                options = options.last_modified_time(time);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        options = options.unix_permissions(metadata.permissions().mode());
+    }
+
+    options
+}
@@ -21,16 +21,31 @@ use crate::{
     common::{self, env_config, parse_config, Action, HistoryData},
     inv, item,
     message::{append_emoji, throw_error, EmojiType, ErrorType},
+    trash,
 };
 use anyhow::Result;
 use chrono::prelude::*;
 use colored::Colorize;
 use rusqlite::Connection;
 use std::{
+    collections::BTreeMap,
     fs,
     path::{Path, PathBuf},
 };
 
+/// Where an item should be according to the history, along with where it was taken from
+pub struct Placement {
+    pub slot: String,
+    pub path: Option<PathBuf>,
+    pub time: i64,
+    /// The action that took it, which orders items by when they were taken
+    pub id: i64,
+    /// Every action made on an item by its name in its slot, including ones from before it was last dropped
+    pub actions: Vec<i64>,
+    /// Whether a symlink to it was left where it was taken from
+    pub link: bool,
+}
+
 /// Undoes actions made by Vento using the history database located on the Vento directory
 pub fn undo(steps: usize) -> Result<()> {
     let path: PathBuf = [
@@ -40,6 +55,8 @@ pub fn undo(steps: usize) -> Result<()> {
     .iter()
     .collect();
     let db = Connection::open(path)?;
+    // Older databases are brought up to date before their actions are read
+    common::history_table(&db)?;
 
     // Determine if step amount is greater than the position of the action
     let mut current = db.prepare("SELECT id FROM history WHERE current = 1")?;
@@ -54,7 +71,7 @@ pub fn undo(steps: usize) -> Result<()> {
 
     // Calculates how many actions need to be undone
     let mut undo_queue_transaction = db.prepare(
-        "SELECT id, path, file, slot, action FROM history WHERE id > ?2 AND id <= ?1 ORDER BY id DESC",
+        "SELECT id, path, file, slot, action, link FROM history WHERE id > ?2 AND id <= ?1 ORDER BY id DESC",
     )?;
     let undo_queue = undo_queue_transaction.query_map([last_action, final_dest], |row| {
         Ok(HistoryData {
@@ -66,17 +83,19 @@ pub fn undo(steps: usize) -> Result<()> {
                 "take" => Action::Take,
                 "drop" => Action::Drop,
                 "switch" => Action::Switch,
+                "trash" => Action::Trash,
                 _ => unreachable!(),
             },
             time: 0,
             current: 0,
+            link: row.get(5)?,
         })
     })?;
 
     // Undoes actions for each step
-    for raw_step in undo_queue {
-        let step = raw_step?;
-
+    // Read in full first, so the database isn't locked while items are moved around
+    let undo_queue: Vec<HistoryData> = undo_queue.collect::<Result<_, _>>()?;
+    for step in undo_queue {
         match step.action {
             Action::Take => {
                 item::drop(
@@ -89,14 +108,21 @@ pub fn undo(steps: usize) -> Result<()> {
                 )?;
             }
             Action::Drop => {
-                let path: String = [
-                    String::from(step.path.unwrap().to_str().unwrap()),
-                    step.file.unwrap(),
-                ]
-                .join("/");
-                item::take(&path, step.slot.unwrap().as_str(), false, false, false)?;
+                retake(
+                    step.path.unwrap(),
+                    &step.file.unwrap(),
+                    &step.slot.unwrap(),
+                    step.link,
+                )?;
             }
             Action::Switch => inv::switch(false, false)?,
+            Action::Trash => {
+                trash::restore(
+                    &step.file.unwrap(),
+                    &step.slot.unwrap(),
+                    &step.path.unwrap(),
+                )?;
+            }
         }
 
         db.execute("UPDATE history SET current = 0 WHERE current = 1", ())?;
@@ -118,10 +144,12 @@ pub fn undo(steps: usize) -> Result<()> {
                 "take" => Action::Take,
                 "drop" => Action::Drop,
                 "switch" => Action::Switch,
+                "trash" => Action::Trash,
                 _ => unreachable!(),
             },
             time: row.get(5)?,
             current: row.get::<_, i32>(5)?,
+            link: false,
         })
     })?;
 
@@ -141,6 +169,7 @@ pub fn undo(steps: usize) -> Result<()> {
             Action::Take => "Take",
             Action::Drop => "Drop",
             Action::Switch => "Switch",
+            Action::Trash => "Trash",
         }
         .bold(),
         " action, on ".green(),
@@ -206,6 +235,8 @@ pub fn redo(steps: usize) -> Result<()> {
     .iter()
     .collect();
     let db = Connection::open(path)?;
+    // Older databases are brought up to date before their actions are read
+    common::history_table(&db)?;
 
     // Determine if step amount is greater than the position of the action
     let mut current = db.prepare("SELECT id FROM history WHERE current = 1")?;
@@ -225,7 +256,7 @@ pub fn redo(steps: usize) -> Result<()> {
 
     // Calculates how many actions need to be redone
     let mut redo_queue_transaction = db.prepare(
-	    "SELECT id, path, file, slot, action FROM history WHERE id > ?1 AND id <= ?2 ORDER BY id ASC",
+	    "SELECT id, path, file, slot, action, link FROM history WHERE id > ?1 AND id <= ?2 ORDER BY id ASC",
 	)?;
     let redo_queue = redo_queue_transaction.query_map([last_action, final_dest], |row| {
         Ok(HistoryData {
@@ -237,25 +268,27 @@ pub fn redo(steps: usize) -> Result<()> {
                 "take" => Action::Take,
                 "drop" => Action::Drop,
                 "switch" => Action::Switch,
+                "trash" => Action::Trash,
                 _ => unreachable!(),
             },
             time: 0,
             current: 0,
+            link: row.get(5)?,
         })
     })?;
 
     // Redoes actions for each step
-    for raw_step in redo_queue {
-        let step = raw_step?;
-
+    // Read in full first, so the database isn't locked while items are moved around
+    let redo_queue: Vec<HistoryData> = redo_queue.collect::<Result<_, _>>()?;
+    for step in redo_queue {
         match step.action {
             Action::Take => {
-                let path: String = [
-                    String::from(step.path.unwrap().to_str().unwrap()),
-                    step.file.unwrap(),
-                ]
-                .join("/");
-                item::take(&path, step.slot.unwrap().as_str(), false, false, false)?;
+                retake(
+                    step.path.unwrap(),
+                    &step.file.unwrap(),
+                    &step.slot.unwrap(),
+                    step.link,
+                )?;
             }
             Action::Drop => {
                 item::drop(
@@ -268,6 +301,14 @@ pub fn redo(steps: usize) -> Result<()> {
                 )?;
             }
             Action::Switch => inv::switch(false, false)?,
+            Action::Trash => {
+                trash::trash(
+                    &step.file.unwrap(),
+                    &step.slot.unwrap(),
+                    &step.path.unwrap(),
+                    false,
+                )?;
+            }
         }
 
         db.execute("UPDATE history SET current = 0 WHERE current = 1", ())?;
@@ -286,10 +327,12 @@ pub fn redo(steps: usize) -> Result<()> {
                 "take" => Action::Take,
                 "drop" => Action::Drop,
                 "switch" => Action::Switch,
+                "trash" => Action::Trash,
                 _ => unreachable!(),
             },
             time: row.get(5)?,
             current: row.get::<_, i32>(5)?,
+            link: false,
         })
     })?;
 
@@ -310,6 +353,7 @@ pub fn redo(steps: usize) -> Result<()> {
             Action::Take => "Take",
             Action::Drop => "Drop",
             Action::Switch => "Switch",
+            Action::Trash => "Trash",
         }
         .bold(),
         " action, on ".green(),
@@ -423,10 +467,12 @@ pub fn view(length: isize) -> Result<()> {
                 "take" => Action::Take,
                 "drop" => Action::Drop,
                 "switch" => Action::Switch,
+                "trash" => Action::Trash,
                 _ => unreachable!(),
             },
             time: row.get(5)?,
             current: row.get(6)?,
+            link: false,
         })
     })?;
 
@@ -577,6 +623,7 @@ pub fn view(length: isize) -> Result<()> {
                 Action::Take => "Take  ",
                 Action::Drop => "Drop  ",
                 Action::Switch => "Switch",
+                Action::Trash => "Trash ",
             },
             path,
             path_pad,
@@ -628,10 +675,12 @@ pub fn migrate() -> Result<()> {
             "take" => Action::Take,
             "drop" => Action::Drop,
             "switch" => Action::Switch,
+            "trash" => Action::Trash,
             _ => unreachable!(),
         },
         time: 0,
         current: 1,
+        link: false,
     })?;
 
     fs::remove_file(last_path)?;
@@ -644,3 +693,159 @@ pub fn migrate() -> Result<()> {
 
     Ok(())
 }
+
+// Takes an item back from a directory into the slot it was in, inside the same directory in the slot if it was in one
+fn retake(path: PathBuf, file: &str, slot: &str, link: bool) -> Result<()> {
+    let file = Path::new(file);
+    let source = path.join(file.file_name().unwrap_or_default());
+    let slot = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => format!("{}:{}", slot, dir.display()),
+        _ => String::from(slot),
+    };
+
+    item::take(
+        &source.to_string_lossy().to_string(),
+        &slot,
+        false,
+        false,
+        false,
+        link,
+    )
+}
+
+/// Lists the items in a slot from the most recently taken one to the least, according to the history
+pub fn stack(slot: &str) -> Result<Vec<String>> {
+    let path = env_config()?.vento_dir.join("history.db3");
+    if !path.is_file() {
+        return Ok(vec![]);
+    }
+    let slot = match slot {
+        "a" | "active" => "active",
+        "t" | "templates" => "templates",
+        _ => "inactive",
+    };
+
+    let mut items: Vec<(String, Placement)> = replay(&Connection::open(path)?)?
+        .into_iter()
+        .filter(|((placed, _), _)| placed == slot)
+        .map(|((_, file), placement)| (file, placement))
+        .collect();
+    items.sort_by_key(|(_, placement)| std::cmp::Reverse(placement.id));
+    Ok(items.into_iter().map(|(file, _)| file).collect())
+}
+
+/// Replays the history up to the current action, finding which slot each item taken should be in by now.
+/// Items are keyed by their slot along with their name, as items in different slots can share a name
+pub fn replay(db: &Connection) -> Result<BTreeMap<(String, String), Placement>> {
+    let mut items: BTreeMap<(String, String), Placement> = BTreeMap::new();
+    let mut done: BTreeMap<(String, String), Vec<i64>> = BTreeMap::new();
+    // Older databases don't have every column read here yet
+    common::history_table(db)?;
+
+    let mut transaction = db.prepare(
+        "SELECT path, file, slot, action, time, id, link FROM history
+            WHERE id <= (SELECT COALESCE(MAX(id), 0) FROM history WHERE current = 1)
+            ORDER BY id ASC",
+    )?;
+    let actions = transaction.query_map([], |row| {
+        Ok((
+            row.get::<_, Option<String>>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, i64>(4)?,
+            row.get::<_, i64>(5)?,
+            row.get::<_, bool>(6)?,
+        ))
+    })?;
+
+    for action in actions {
+        let (path, file, slot, action, time, id, link) = action?;
+        let slot = match slot.as_deref() {
+            Some("a" | "active") => "active",
+            Some("i" | "inactive") => "inactive",
+            Some("t" | "templates") => "templates",
+            _ => "",
+        };
+
+        match (action.as_str(), file) {
+            ("take", Some(file)) => {
+                let key = (String::from(slot), file);
+                let actions = done.entry(key.clone()).or_default();
+                actions.push(id);
+                items.insert(
+                    key,
+                    Placement {
+                        slot: String::from(slot),
+                        path: path.map(PathBuf::from),
+                        time,
+                        id,
+                        actions: actions.clone(),
+                        link,
+                    },
+                );
+            }
+            ("drop" | "trash", Some(file)) => {
+                let key = (String::from(slot), file);
+                done.entry(key.clone()).or_default().push(id);
+                items.remove(&key);
+            }
+            // Switching moves every item into the other slot, leaving templates where they are
+            ("switch", _) => {
+                items = items
+                    .into_iter()
+                    .map(|((slot, file), mut placement)| {
+                        placement.slot = String::from(switched(&slot));
+                        ((placement.slot.clone(), file), placement)
+                    })
+                    .collect();
+                done = done
+                    .into_iter()
+                    .map(|((slot, file), actions)| ((String::from(switched(&slot)), file), actions))
+                    .collect();
+            }
+            _ => (),
+        }
+    }
+
+    Ok(items)
+}
+
+// Which slot an item in a slot ends up in after switching them
+fn switched(slot: &str) -> &str {
+    match slot {
+        "active" => "inactive",
+        "inactive" => "active",
+        slot => slot,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_items_sharing_a_name_across_slots() {
+        let db = Connection::open_in_memory().unwrap();
+        common::history_table(&db).unwrap();
+        db.execute_batch(
+            "INSERT INTO history (path, file, slot, action, time, current) VALUES
+                ('/tmp', 'same', 'active', 'take', 0, 0),
+                ('/tmp', 'same', 'inactive', 'take', 0, 0),
+                ('/tmp', 'same', 'inactive', 'drop', 0, 0),
+                ('/tmp', 'other', 'inactive', 'take', 0, 0),
+                (NULL, NULL, NULL, 'switch', 0, 1);",
+        )
+        .unwrap();
+
+        let items = replay(&db).unwrap();
+        let keys: Vec<(&str, &str)> = items
+            .keys()
+            .map(|(slot, file)| (slot.as_str(), file.as_str()))
+            .collect();
+        assert_eq!(keys, [("active", "other"), ("inactive", "same")]);
+        let same = &items[&(String::from("inactive"), String::from("same"))];
+        assert_eq!(same.slot, "inactive");
+        assert_eq!(same.actions, [1]);
+    }
+}
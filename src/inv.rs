@@ -18,30 +18,155 @@
  */
 
 use super::{
-    common,
+    archive, common, dedup,
+    format::{Compression, Format},
+    history::{self, Placement},
     message::{append_emoji, throw_error, EmojiType, ErrorType},
+    meta::{self, Metadata},
+    snapshot::{self, Kind},
+    store::{self, SlotStore},
+    template, trash,
 };
 use anyhow::{bail, Context, Result};
+use chrono::Local;
 use colored::Colorize;
+use rusqlite::Connection;
 use size_format::SizeFormatterBinary;
+use std::collections::BTreeMap;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{fs, process};
 
-/// Initializes Vento by creating the respective directories it will use
-pub fn init() -> Result<()> {
+/// Kinds of items shown in listings, in the order sorting by type puts them
+#[derive(Clone, Copy)]
+pub enum ItemKind {
+    Directory,
+    Symlink,
+    File,
+}
+
+/// What to sort listings by
+#[derive(Clone, Copy, Default)]
+pub enum Sort {
+    #[default]
+    Name,
+    Size,
+    Modified,
+    Type,
+}
+
+impl FromStr for Sort {
+    type Err = anyhow::Error;
+
+    fn from_str(sort: &str) -> Result<Self> {
+        match sort {
+            "name" => Ok(Sort::Name),
+            "size" => Ok(Sort::Size),
+            "mtime" => Ok(Sort::Modified),
+            "type" => Ok(Sort::Type),
+            _ => bail!(
+                "{}",
+                format!(
+                    "No such sorting order. Valid orders are {}, {}, {} and {}",
+                    "name".bold(),
+                    "size".bold(),
+                    "mtime".bold(),
+                    "type".bold()
+                )
+                .red()
+            ),
+        }
+    }
+}
+
+/// How to list the items in a slot
+#[derive(Default)]
+pub struct ListOptions {
+    /// List the items inside of directories too
+    pub tree: bool,
+    /// How many levels deep to list when listing a tree
+    pub depth: Option<usize>,
+    pub sort: Sort,
+    pub reverse: bool,
+    /// Show how long ago each item was taken, and where from
+    pub origin: bool,
+    /// Flag items taken longer ago than this many seconds, overriding the config file
+    pub stale_after: Option<u64>,
+}
+
+/// Initializes Vento by creating the respective directories it will use. Reinitializing backs the old directory up first, optionally keeping the history or the slots
+pub fn init(keep_history: bool, keep_slots: bool) -> Result<()> {
     let ventodir = &common::env_config()?.vento_dir;
 
     if ventodir.is_dir() {
-        // Checks if Vento has already been initialized and prompts the user if they want to initialize it again
+        if keep_history && keep_slots {
+            bail!(
+                "{}",
+                "Keeping both the history and the slots leaves nothing to reinitialize".red()
+            );
+        }
+
+        let resetting = match (keep_history, keep_slots) {
+            (true, _) => "every slot",
+            (_, true) => "the history",
+            _ => "all files on the directory for Vento",
+        };
+        let name = ventodir
+            .file_name()
+            .unwrap_or(ventodir.as_os_str())
+            .to_string_lossy()
+            .to_string();
+
+        // Checks if Vento has already been initialized and prompts the user if they want to initialize it again.
+        // Throwing away items takes typing the directory's name rather than a single key. Trashed items only go along with the backup when it's moved
+        let trashed = common::parse_config()?.init_backup == "move"
+            && has_items(&ventodir.join(trash::TRASH))?;
+        let filled = trashed
+            || !keep_slots
+                && (has_items(&common::env_config()?.active_dir)?
+                    || has_items(&common::env_config()?.inactive_dir)?
+                    || has_items(&common::env_config()?.templates_dir)?);
         let mut answer = String::new();
-        print!("{}{} Vento has already been initialized. Reinitializing will delete all files on the directory for Vento. Do you wish to proceed? (y/N) ", append_emoji(EmojiType::Warning)?, "WARNING:".bold().red());
+        match filled {
+            true => print!("{}{} Vento has already been initialized and its inventories or trash aren't empty. Reinitializing will reset {}, moving the old files into a backup. Type {} to proceed: ", append_emoji(EmojiType::Warning)?, "WARNING:".bold().red(), resetting, name.bold()),
+            false => print!("{}{} Vento has already been initialized. Reinitializing will reset {}, moving the old files into a backup. Do you wish to proceed? (y/N) ", append_emoji(EmojiType::Warning)?, "WARNING:".bold().red(), resetting),
+        };
         let _ = io::stdout().flush();
         io::stdin().read_line(&mut answer)?;
-        match answer.as_str().trim() {
-            "y" | "Y" => fs::remove_dir_all(ventodir)?,
+        match (filled, answer.as_str().trim()) {
+            (true, answer) if answer == name => (),
+            (false, "y" | "Y") => (),
             _ => process::exit(0),
         };
+
+        snapshot::auto(true)?;
+        let mut kept = vec![];
+        if keep_slots {
+            kept.extend(["active", "inactive", template::TEMPLATES]);
+        }
+        // The metadata of items is kept in the same database as the history, so it's backed up
+        // and stays in place when either is kept, with only the part being reset cleared from it
+        let shared: &[&str] = match keep_history || keep_slots {
+            true => &["history.db3"],
+            false => &[],
+        };
+        let backup = backup(ventodir, &kept, shared)?;
+        if !shared.is_empty() {
+            reset_database(
+                &Connection::open(ventodir.join("history.db3"))?,
+                keep_history,
+                keep_slots,
+            )?;
+        }
+
+        println!(
+            "{}{} {}",
+            append_emoji(EmojiType::Success)?,
+            "Backed up the old Vento directory into".green(),
+            backup.display()
+        );
     };
 
     create_slots()?;
@@ -49,7 +174,7 @@ pub fn init() -> Result<()> {
 }
 
 /// Lists files in the provided slot and/or directory
-pub fn list(slot: &str, dir: &str, display_slot: bool) -> Result<()> {
+pub fn list(slot: &str, dir: &str, display_slot: bool, options: &ListOptions) -> Result<()> {
     let ventodir = &common::env_config()?.vento_dir;
 
     if !ventodir.is_dir() {
@@ -57,36 +182,38 @@ pub fn list(slot: &str, dir: &str, display_slot: bool) -> Result<()> {
         throw_error(ErrorType::NotInitialized)?;
     }
 
-    let mut slotdir: PathBuf = match slot {
-        "active" | "a" => common::env_config()?.active_dir,
-        "inactive" | "i" => common::env_config()?.inactive_dir,
-        _ => PathBuf::new(),
-    };
+    // Basically preventing from listing anything out of bounds. ls and dir exist for that
+    store::check_path(Path::new(dir))?;
 
-    if !dir.is_empty() {
-        // Detects if the directory argument is not empty, and if so appends the path provided to the slot directory variable
-        slotdir = [&slotdir, &Path::new(dir).to_path_buf()].iter().collect();
-    }
-
-    if dir.to_string().contains("..") {
-        // Basically preventing from listing anything out of bounds. ls and dir exist for that
-        throw_error(ErrorType::NoAccessParent)?;
-    }
-
-    if !slotdir.is_dir() {
-        // Detects if the consulted slot or directory exists
+    // Detects if the consulted slot or directory exists
+    let found = match slot {
+        "active" | "a" | "inactive" | "i" | "templates" | "t" => {
+            let store = store::open(slot)?;
+            let is_dir = dir.is_empty()
+                || store
+                    .metadata(Path::new(dir))?
+                    .is_some_and(|entry| matches!(entry.kind, ItemKind::Directory));
+            match is_dir {
+                true => read_entries(store.as_ref(), Path::new(dir), options).ok(),
+                false => None,
+            }
+        }
+        _ => None,
+    };
+    let Some(entries) = found else {
         bail!(
             "{}",
             format!(
-                "No such slot or directory. Valid slots are {} and {}",
+                "No such slot or directory. Valid slots are {}, {} and {}",
                 "active".green().bold(),
-                "inactive".blue().bold()
+                "inactive".blue().bold(),
+                "templates".magenta().bold()
             )
             .red()
         );
     };
 
-    if fs::read_dir(&slotdir).unwrap().count() == 0 {
+    if entries.is_empty() {
         // Detects if the slot or directory has any contents
         println!(
             "{}{}",
@@ -96,6 +223,7 @@ pub fn list(slot: &str, dir: &str, display_slot: bool) -> Result<()> {
                 if display_slot || !dir.is_empty() {
                     match slot {
                         "active" => slot.bold(),
+                        "templates" | "t" => slot.magenta().bold(),
                         _ => slot.blue().bold(),
                     }
                 } else {
@@ -124,6 +252,7 @@ pub fn list(slot: &str, dir: &str, display_slot: bool) -> Result<()> {
                         " {}",
                         match slot {
                             "active" => slot.bold(),
+                            "templates" | "t" => slot.magenta().bold(),
                             _ => slot.blue().bold(),
                         },
                     )
@@ -139,44 +268,243 @@ pub fn list(slot: &str, dir: &str, display_slot: bool) -> Result<()> {
                 } else {
                     " inventory".to_string()
                 },
-                format!("{}", fs::read_dir(&slotdir).unwrap().count())
-                    .white()
-                    .bold()
+                format!("{}", entries.len()).white().bold()
             )
             .green()
         );
-        for file in fs::read_dir(&slotdir).unwrap() {
-            let file = file.unwrap().path();
-
-            println!(
-                "   - [{}] {}{}",
-                if file.clone().is_dir() {
-                    "D".blue()
-                } else if file.clone().is_symlink() {
-                    "S".yellow()
-                } else {
-                    "F".green()
+
+        // Items are found in the metadata and the history by their path inside the slot.
+        // The history is only read if it's going to be shown
+        let metadata = meta::slot(slot)?;
+        let stale_after = options
+            .stale_after
+            .or(common::parse_config()?.list_stale_after);
+        let placements = match options.origin || stale_after.is_some() {
+            true => placements(slot)?,
+            false => Default::default(),
+        };
+        let details = Details {
+            metadata,
+            placements,
+            stale_after,
+            now: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64,
+        };
+
+        print_entries(&entries, Path::new(dir), options, &details, 0)?;
+
+        let (count, size, stored_size) = totals(&entries);
+        println!(
+            "{}",
+            format!(
+                "{} {}, {}B in total{}",
+                count,
+                match count {
+                    1 => "item",
+                    _ => "items",
                 },
-                file.clone()
-                    .file_name()
-                    .unwrap()
-                    .to_os_string()
-                    .into_string()
-                    .unwrap(),
-                if file.clone().is_file() {
-                    format!(
-                        " ({}B)",
-                        SizeFormatterBinary::new(file.clone().metadata().unwrap().len())
-                    )
-                } else {
-                    String::new()
+                SizeFormatterBinary::new(size),
+                match stored_size != size {
+                    true => format!(", {}B on disk", SizeFormatterBinary::new(stored_size)),
+                    false => String::new(),
                 }
-            );
+            )
+            .dimmed()
+        );
+    }
+    Ok(())
+}
+
+// An item inside a slot, along with everything inside of it when listing a tree
+struct Listed {
+    name: String,
+    kind: ItemKind,
+    size: u64,
+    stored_size: u64,
+    modified: Option<SystemTime>,
+    children: Vec<Listed>,
+}
+
+// Reads the items inside a directory, sorted, measuring directories by everything inside of them
+fn read_entries(store: &dyn SlotStore, dir: &Path, options: &ListOptions) -> Result<Vec<Listed>> {
+    let mut entries = vec![];
+
+    for entry in store.list(dir)? {
+        let (size, stored_size, children) = match entry.kind {
+            ItemKind::Directory => {
+                let children = read_entries(store, &dir.join(&entry.name), options)?;
+                (
+                    children.iter().map(|child| child.size).sum(),
+                    children.iter().map(|child| child.stored_size).sum(),
+                    children,
+                )
+            }
+            _ => (entry.size, entry.stored_size, vec![]),
+        };
+
+        entries.push(Listed {
+            name: entry.name,
+            kind: entry.kind,
+            size,
+            stored_size,
+            modified: entry.modified,
+            children,
+        });
+    }
+
+    entries.sort_by(|a, b| {
+        let order = match options.sort {
+            Sort::Name => a.name.cmp(&b.name),
+            // Biggest and newest items go first
+            Sort::Size => b.size.cmp(&a.size),
+            Sort::Modified => b.modified.cmp(&a.modified),
+            Sort::Type => (a.kind as u8).cmp(&(b.kind as u8)),
+        };
+        order.then_with(|| a.name.cmp(&b.name))
+    });
+    if options.reverse {
+        entries.reverse();
+    }
+    Ok(entries)
+}
+
+// What's known about the items at the top of a slot, beyond what's in the slot itself
+struct Details {
+    metadata: BTreeMap<String, Metadata>,
+    placements: BTreeMap<String, Placement>,
+    stale_after: Option<u64>,
+    now: i64,
+}
+
+impl Details {
+    // Describes when and where from an item was taken, flagging it if it has been around for too long
+    fn describe(&self, name: &str, options: &ListOptions) -> String {
+        let mut description = String::new();
+        let Some(placement) = self.placements.get(name) else {
+            return description;
+        };
+        let age = (self.now - placement.time).max(0) as u64;
+
+        if options.origin {
+            description.push_str(&format!(
+                " {}",
+                match &placement.path {
+                    Some(path) => format!(
+                        "taken {} ago from {}",
+                        common::format_duration(age),
+                        path.display()
+                    ),
+                    None => format!("taken {} ago", common::format_duration(age)),
+                }
+                .dimmed()
+            ));
+        }
+        if self
+            .stale_after
+            .is_some_and(|stale_after| age > stale_after)
+        {
+            description.push_str(&format!(" {}", "(stale)".yellow().bold()));
+        }
+        description
+    }
+}
+
+// Finds when and where from every item in a slot was taken, according to the history
+fn placements(slot: &str) -> Result<BTreeMap<String, Placement>> {
+    let path = common::env_config()?.vento_dir.join("history.db3");
+    if !path.is_file() {
+        return Ok(BTreeMap::new());
+    }
+    let slot = match slot {
+        "a" | "active" => "active",
+        "i" | "inactive" => "inactive",
+        "t" | "templates" => "templates",
+        _ => slot,
+    };
+
+    Ok(history::replay(&Connection::open(path)?)?
+        .into_iter()
+        .filter(|((placed, _), _)| placed == slot)
+        .map(|((_, file), placement)| (file, placement))
+        .collect())
+}
+
+fn print_entries(
+    entries: &[Listed],
+    dir: &Path,
+    options: &ListOptions,
+    details: &Details,
+    depth: usize,
+) -> Result<()> {
+    for entry in entries {
+        let path = dir.join(&entry.name);
+        let name = path.to_string_lossy();
+        println!(
+            "{}{}{}{}{}",
+            "  ".repeat(depth),
+            format_item(
+                entry.kind,
+                &entry.name,
+                match entry.kind {
+                    ItemKind::Symlink => None,
+                    _ => Some(entry.size),
+                }
+            ),
+            // Compressed items take up less space than they will once they're dropped
+            match entry.stored_size != entry.size {
+                true => format!(
+                    " {}",
+                    format!("{}B on disk", SizeFormatterBinary::new(entry.stored_size)).dimmed()
+                ),
+                false => String::new(),
+            },
+            match details.metadata.get(name.as_ref()) {
+                Some(metadata) => metadata.describe(),
+                None => String::new(),
+            },
+            details.describe(&name, options)
+        );
+
+        if options.tree && options.depth.is_none_or(|max| depth + 1 < max) {
+            print_entries(&entry.children, &path, options, details, depth + 1)?;
         }
     }
     Ok(())
 }
 
+// Counts every item inside a list of entries, along with their size and how much space they take up on disk
+fn totals(entries: &[Listed]) -> (usize, u64, u64) {
+    entries
+        .iter()
+        .fold((0, 0, 0), |(count, size, stored_size), entry| {
+            let (children, _, _) = totals(&entry.children);
+            (
+                count + 1 + children,
+                size + entry.size,
+                stored_size + entry.stored_size,
+            )
+        })
+}
+
+/// Formats an item the same way inventory listings display it
+pub fn format_item(kind: ItemKind, name: &str, size: Option<u64>) -> String {
+    format!(
+        "   - [{}] {}{}",
+        match kind {
+            ItemKind::Directory => "D".blue(),
+            ItemKind::Symlink => "S".yellow(),
+            ItemKind::File => "F".green(),
+        },
+        name,
+        match size {
+            Some(size) => format!(" ({}B)", SizeFormatterBinary::new(size)),
+            None => String::new(),
+        }
+    )
+}
+
 /// Switches inevntory slots between each other, making the currently active inventory inactive and viceversa
 pub fn switch(message: bool, save_history: bool) -> Result<()> {
     let ventodir = &common::env_config()?.vento_dir;
@@ -186,11 +514,12 @@ pub fn switch(message: bool, save_history: bool) -> Result<()> {
         .iter()
         .collect();
 
-    let rename_error = "Vento was unable to switch slots. Try running \"vento -i\" and try again";
+    let rename_error = "Vento was unable to switch slots. Run \"vento --doctor\" to find out what went wrong and recover the slots";
 
     fs::rename(active, &temp).context(rename_error)?;
     fs::rename(inactive, active).context(rename_error)?;
     fs::rename(&temp, inactive).context(rename_error)?;
+    meta::switch()?;
 
     if save_history {
         common::history(common::HistoryData {
@@ -201,6 +530,7 @@ pub fn switch(message: bool, save_history: bool) -> Result<()> {
             action: common::Action::Switch,
             current: 1,
             time: 0,
+            link: false,
         })?;
     }
 
@@ -214,6 +544,105 @@ pub fn switch(message: bool, save_history: bool) -> Result<()> {
     Ok(())
 }
 
+// Clears the history or the metadata of the items in the slots, depending on which of them is being reset
+fn reset_database(db: &Connection, keep_history: bool, keep_slots: bool) -> Result<()> {
+    if !keep_history {
+        common::history_table(db)?;
+        db.execute("DELETE FROM history", ())?;
+    }
+    if !keep_slots {
+        meta::forget_slots(db)?;
+    }
+    Ok(())
+}
+
+/// Backs up everything being reset out of the Vento directory, next to it with a timestamp.
+/// What's kept stays where it is, while what's shared is backed up and stays where it is too
+fn backup(ventodir: &Path, kept: &[&str], shared: &[&str]) -> Result<PathBuf> {
+    let config = common::parse_config()?;
+    let stamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+
+    // Finds a name next to the Vento directory no other backup has taken
+    let name = |extension: &str| {
+        let mut count = 1;
+        loop {
+            let backup = PathBuf::from(format!(
+                "{}.bak-{}{}{}",
+                ventodir.display(),
+                stamp,
+                match count {
+                    1 => String::new(),
+                    _ => format!("-{}", count),
+                },
+                extension
+            ));
+            if fs::symlink_metadata(&backup).is_err() {
+                return backup;
+            }
+            count += 1;
+        }
+    };
+
+    match config.init_backup.as_str() {
+        "archive" => {
+            let format: Format = config.archive_format.parse()?;
+            let output = name(&format!(".{}", format.extension()));
+            let compression = Compression::new(Some(format), &output, None, None, None)?;
+            archive::export_dir(output.clone(), &compression, Kind::Full, false, false)?;
+
+            // Whatever the archive leaves out, such as the trash and older backups, stays where it is
+            for item in fs::read_dir(ventodir)? {
+                let item = item?;
+                if kept
+                    .iter()
+                    .chain(shared)
+                    .chain(archive::EXCLUDED)
+                    .any(|name| item.file_name() == *name)
+                {
+                    continue;
+                }
+                match item.file_type()?.is_dir() {
+                    true => fs::remove_dir_all(item.path())?,
+                    false => fs::remove_file(item.path())?,
+                }
+            }
+            dedup::collect()?;
+            Ok(output)
+        }
+        "move" => {
+            let backup = name("");
+            fs::rename(ventodir, &backup)?;
+            fs::create_dir_all(ventodir)?;
+
+            for name in kept {
+                if fs::symlink_metadata(backup.join(name)).is_ok() {
+                    fs::rename(backup.join(name), ventodir.join(name))?;
+                }
+            }
+            for name in shared {
+                if backup.join(name).is_file() {
+                    fs::copy(backup.join(name), ventodir.join(name))?;
+                }
+            }
+            Ok(backup)
+        }
+        _ => bail!(
+            "{}",
+            format!(
+                "No such backup method for init.backup. Valid methods are {} and {}",
+                "move".bold(),
+                "archive".bold()
+            )
+            .red()
+        ),
+    }
+}
+
+// Checks if a slot has anything in it
+fn has_items(slotdir: &Path) -> Result<bool> {
+    Ok(slotdir.is_dir() && fs::read_dir(slotdir)?.next().is_some())
+}
+
 // Used only on init. Creates all required directories
 fn create_slots() -> Result<()> {
     let active = &common::env_config()?.active_dir;
@@ -221,6 +650,7 @@ fn create_slots() -> Result<()> {
 
     fs::create_dir_all(active)?;
     fs::create_dir_all(inactive)?;
+    fs::create_dir_all(&common::env_config()?.templates_dir)?;
 
     println!(
         "{}{}",
@@ -229,3 +659,83 @@ fn create_slots() -> Result<()> {
     );
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        item,
+        store::{scratch, MemStore},
+    };
+
+    #[test]
+    fn lists_items_sorted_and_measured() {
+        let dir = scratch("lists");
+        let store = MemStore::default();
+        fs::create_dir_all(dir.join("docs/old")).unwrap();
+        fs::write(dir.join("docs/notes.md"), "hello").unwrap();
+        fs::write(dir.join("docs/old/draft.md"), "hi").unwrap();
+        fs::write(dir.join("big.txt"), "0123456789").unwrap();
+        item::put(&store, &dir.join("docs"), "").unwrap();
+        item::put(&store, &dir.join("big.txt"), "").unwrap();
+
+        let entries = read_entries(&store, Path::new(""), &ListOptions::default()).unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["big.txt", "docs"]);
+        assert_eq!(entries[1].size, 7);
+        assert_eq!(entries[1].children.len(), 2);
+        assert_eq!(totals(&entries).0, 5);
+
+        let options = ListOptions {
+            sort: Sort::Size,
+            ..Default::default()
+        };
+        let entries = read_entries(&store, Path::new("docs"), &options).unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["notes.md", "old"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // A database with one action in the history, and metadata for an item in a slot and a dropped one
+    fn database() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        common::history_table(&db).unwrap();
+        meta::table(&db).unwrap();
+        db.execute_batch(
+            "INSERT INTO history (path, file, slot, action, time, current)
+                VALUES ('/tmp', 'kept.txt', 'active', 'take', 0, 1);
+            INSERT INTO metadata (slot, file, tags) VALUES
+                ('active', 'kept.txt', 'ci'),
+                ('dropped', '/tmp/old.txt', 'ci');",
+        )
+        .unwrap();
+        db
+    }
+
+    fn count(db: &Connection, query: &str) -> i64 {
+        db.query_row(query, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn reset_keeping_slots_keeps_their_metadata() {
+        let db = database();
+        reset_database(&db, false, true).unwrap();
+
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM history"), 0);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM metadata"), 2);
+    }
+
+    #[test]
+    fn reset_keeping_history_forgets_metadata_of_slots() {
+        let db = database();
+        reset_database(&db, true, false).unwrap();
+
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM history"), 1);
+        assert_eq!(
+            count(&db, "SELECT COUNT(*) FROM metadata WHERE slot = 'dropped'"),
+            1
+        );
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM metadata"), 1);
+    }
+}
@@ -19,21 +19,29 @@
 
 use super::{
     common::{env_config, history, parse_config, Action, HistoryData},
+    dedup::{self, Savings},
+    history,
     message::{append_emoji, throw_error, EmojiType, ErrorType},
+    meta,
+    store::{self, SlotStore},
+    template,
 };
 use anyhow::{bail, Result};
 use colored::Colorize;
-use fs_extra::dir::{move_dir, CopyOptions};
 use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
 use std::path::{Path, PathBuf};
 
-/// Takes a file or directory and stores it in an inventory slot
+/// Takes a file or directory and stores it in an inventory slot, or in a directory inside of it if the slot is given
+/// as a location such as active:docs
 pub fn take(
     file: &String,
     slot: &str,
     message: bool,
     display_slot: bool,
     save_history: bool,
+    link: bool,
 ) -> Result<()> {
     let ventodir = &env_config()?.vento_dir;
 
@@ -41,58 +49,56 @@ pub fn take(
         // Detects if Vento hasn't been initialized and bails if so
         throw_error(ErrorType::NotInitialized)?;
     };
-    let slotdir: PathBuf = match slot {
-        "active" | "a" => env_config()?.active_dir,
-        "inactive" | "i" => env_config()?.inactive_dir,
-        _ => PathBuf::new(),
-    };
+    let (slot, dir) = store::parse_location(slot).unwrap_or((slot, ""));
+    let store = store::open(slot)?;
 
-    if !slotdir.is_dir() {
-        // Detects if the slot provided exists
-        bail!(
+    // Compressed files aren't stored under their own name, so there'd be nothing for the link to point to
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    let slotdir = match (link, store.path()) {
+        (false, _) => None,
+        (true, _) if !cfg!(unix) => {
+            bail!("{}", "Items can only be linked to on Unix systems".red())
+        }
+        (true, Some(slotdir)) if !store::compressed(slot)? => Some(slotdir.to_path_buf()),
+        _ => bail!(
             "{}",
-            format!(
-                "No such slot. Valid slots are {} and {}",
-                "active".green().bold(),
-                "inactive".blue().bold()
-            )
-            .red()
-        );
+            "Items can only be linked to in slots which aren't compressed".red()
+        ),
     };
 
     let sourcepath: PathBuf = Path::new(&file).to_path_buf();
     let mut sourcelocation: PathBuf = fs::canonicalize(&sourcepath)?;
     sourcelocation.pop();
     let filename = Path::new(&file).file_name().unwrap().to_str().unwrap();
-    let destpath: PathBuf = [&slotdir, &Path::new(&filename).to_path_buf()]
-        .iter()
-        .collect();
 
-    if Path::exists(&destpath) {
-        // Checks if there's a file with the same name in the inventory.
-        throw_error(ErrorType::ExistsInventory)?;
+    let name = put(store.as_ref(), &sourcepath, dir)?;
+
+    // Leaves a placeholder behind, so whatever refers to the item still finds it
+    #[cfg(unix)]
+    if let Some(slotdir) = &slotdir {
+        symlink(slotdir.join(&name), sourcelocation.join(filename))?;
     }
 
-    if sourcepath.is_file() | sourcepath.is_symlink() {
-        // Checks the path's file type
-        fs::copy(file, &destpath)?;
-        fs::remove_file(file)?;
-    } else if sourcepath.is_dir() {
-        let options = CopyOptions::new();
-        move_dir(file, &slotdir, &options)?;
-    } else {
-        throw_error(ErrorType::NoFileOrDir)?;
+    meta::taken(&sourcelocation.join(filename), slot, &name)?;
+    // Linked items are changed through their link, so they keep their own copy rather than one shared with other files
+    if let (true, false, Some(slotdir)) = (dedup::enabled()?, link, store.path()) {
+        let stored = match slotdir.join(&name) {
+            path if fs::symlink_metadata(&path).is_ok() => path,
+            path => store::compressed_path(&path),
+        };
+        dedup::store(&stored, &mut Savings::default())?;
     }
 
     if save_history {
         history(HistoryData {
             id: 0,
             path: Some(sourcelocation.clone()),
-            file: Some(String::from(filename)),
+            file: Some(name.clone()),
             slot: Some(String::from(slot)),
             action: Action::Take,
             current: 1,
             time: 0,
+            link,
         })?;
     }
 
@@ -101,7 +107,7 @@ pub fn take(
             "{}{} {}{}{}",
             append_emoji(EmojiType::Success)?,
             "Took".green(),
-            &filename.bold(),
+            &name.bold(),
             match parse_config()?.display_dir {
                 true => format! {"{} {}",
                     " from".green(),
@@ -129,7 +135,7 @@ pub fn take(
     Ok(())
 }
 
-/// Drops a file or directory and stores it in an inventory slot
+/// Drops a file or directory out of an inventory slot, which can be inside a directory in it such as docs/notes.md
 pub fn drop(
     file: &String,
     slot: &str,
@@ -146,50 +152,20 @@ pub fn drop(
         throw_error(ErrorType::NotInitialized)?;
     };
 
-    let slotdir: PathBuf = match slot {
-        "active" | "a" => env_config()?.active_dir,
-        "inactive" | "i" => env_config()?.inactive_dir,
-        _ => PathBuf::new(),
-    };
+    let store = store::open(slot)?;
+    store::check_path(Path::new(file))?;
 
-    if !slotdir.is_dir() {
-        // Detects if the slot provided exists
-        bail!(
-            "{}",
-            format!(
-                "No such slot. Valid slots are {} and {}",
-                "active".green().bold(),
-                "inactive".blue().bold()
-            )
-            .red()
-        );
-    };
-
-    let sourcepath: PathBuf = [&slotdir, &Path::new(file).to_path_buf()].iter().collect();
-    let mut destpath: PathBuf = [
-        Path::new(&dest).to_path_buf(),
-        Path::new(file).to_path_buf(),
-    ]
-    .iter()
-    .collect();
-
-    if Path::exists(&destpath) {
-        // Checks if there's a file with the same name in the destination path.
-        throw_error(ErrorType::ExistsDestination)?;
+    // The placeholder left when the item was taken gives way to the item itself
+    let filename = Path::new(file).file_name().unwrap_or_default();
+    let placeholder = dest.join(filename);
+    let link = store.exists(Path::new(file))? && is_placeholder(&placeholder, file)?;
+    if link {
+        fs::remove_file(&placeholder)?;
     }
 
-    if sourcepath.is_file() | sourcepath.is_symlink() {
-        // Checks the path's file type
-        fs::copy(&sourcepath, &destpath)?;
-        fs::remove_file(&sourcepath)?;
-    } else if sourcepath.is_dir() {
-        let destpath: PathBuf = Path::new(&dest).to_path_buf();
-        let options = CopyOptions::new();
-        move_dir(&sourcepath, destpath, &options)?;
-    } else {
-        throw_error(ErrorType::NoFileOrDir)?;
-    }
+    let mut destpath = get(store.as_ref(), file, &dest)?;
 
+    meta::dropped(slot, file, &destpath)?;
     destpath.pop();
 
     if save_history {
@@ -201,6 +177,7 @@ pub fn drop(
             action: Action::Drop,
             current: 1,
             time: 0,
+            link,
         })?;
     }
 
@@ -236,3 +213,223 @@ pub fn drop(
 
     Ok(())
 }
+
+/// Drops the items most recently taken into a slot, last one first, into a destination
+pub fn pop(
+    count: usize,
+    slot: &str,
+    dest: PathBuf,
+    message: bool,
+    display_slot: bool,
+) -> Result<()> {
+    if !env_config()?.vento_dir.is_dir() {
+        throw_error(ErrorType::NotInitialized)?;
+    }
+    if template::is_templates(slot) {
+        bail!("{}", "Templates can only be dropped by name".red());
+    }
+    if !dest.is_dir() {
+        bail!(
+            "{}",
+            format!(
+                "Can't pop into {}, since it isn't a directory",
+                dest.display()
+            )
+            .red()
+        );
+    }
+
+    // Items the history knows about which have gone missing since can't be popped
+    let store = store::open(slot)?;
+    let mut stack = vec![];
+    for file in history::stack(slot)? {
+        if store.exists(Path::new(&file))? {
+            stack.push(file);
+        }
+    }
+
+    if stack.is_empty() {
+        bail!(
+            "{}",
+            "There's nothing to pop, since no items in this slot were taken".red()
+        );
+    }
+    if count > stack.len() {
+        bail!(
+            "{}",
+            format!(
+                "Can't pop {} items, since only {} items in this slot were taken",
+                count,
+                stack.len()
+            )
+            .red()
+        );
+    }
+
+    for file in stack.iter().take(count) {
+        drop(file, slot, dest.clone(), message, display_slot, true)?;
+    }
+    Ok(())
+}
+
+/// Puts a file or directory into a slot's store, inside a directory in it unless it's empty, returning the name it's
+/// kept under
+pub fn put(store: &dyn SlotStore, source: &Path, dir: &str) -> Result<String> {
+    store::check_path(Path::new(dir))?;
+    let filename = source.file_name().unwrap_or_default();
+    // Where the item is kept inside the slot, which is only its name unless it's kept inside a directory
+    let name = Path::new(dir).join(filename).to_string_lossy().to_string();
+
+    if store.exists(Path::new(&name))? {
+        // Checks if there's a file with the same name in the inventory.
+        throw_error(ErrorType::ExistsInventory)?;
+    }
+
+    store.put(source, &name)?;
+    Ok(name)
+}
+
+/// Gets an item out of a slot's store into a destination directory, by its own name even if it's inside a directory
+/// in the slot, returning the path it ends up at
+pub fn get(store: &dyn SlotStore, file: &str, dest: &Path) -> Result<PathBuf> {
+    store::check_path(Path::new(file))?;
+    if !store.exists(Path::new(file))? {
+        throw_error(ErrorType::NoFileOrDir)?;
+    }
+
+    let destpath = dest.join(Path::new(file).file_name().unwrap_or_default());
+    if fs::symlink_metadata(&destpath).is_ok() {
+        // Checks if there's a file with the same name in the destination path.
+        throw_error(ErrorType::ExistsDestination)?;
+    }
+
+    store.get(file, &destpath)?;
+    Ok(destpath)
+}
+
+// Checks whether a path is a symlink left behind by taking an item, pointing at where it's kept in either slot
+fn is_placeholder(path: &Path, file: &str) -> Result<bool> {
+    let Ok(target) = fs::read_link(path) else {
+        return Ok(false);
+    };
+    let settings = env_config()?;
+    Ok([settings.active_dir, settings.inactive_dir]
+        .iter()
+        .any(|slotdir| target == slotdir.join(file)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{scratch, MemStore};
+
+    #[test]
+    fn takes_and_drops_items() {
+        let dir = scratch("takes-and-drops");
+        let store = MemStore::default();
+        fs::write(dir.join("notes.md"), "hello").unwrap();
+
+        let name = put(&store, &dir.join("notes.md"), "").unwrap();
+        assert_eq!(name, "notes.md");
+        assert!(!dir.join("notes.md").exists());
+        assert!(store.exists(Path::new("notes.md")).unwrap());
+
+        let dest = get(&store, "notes.md", &dir).unwrap();
+        assert_eq!(dest, dir.join("notes.md"));
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "hello");
+        assert!(!store.exists(Path::new("notes.md")).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn takes_and_drops_items_inside_directories() {
+        let dir = scratch("nested");
+        let store = MemStore::default();
+        fs::create_dir(dir.join("docs")).unwrap();
+        put(&store, &dir.join("docs"), "").unwrap();
+        fs::write(dir.join("notes.md"), "hello").unwrap();
+
+        let name = put(&store, &dir.join("notes.md"), "docs").unwrap();
+        assert_eq!(name, "docs/notes.md");
+        assert!(store.exists(Path::new("docs/notes.md")).unwrap());
+
+        let dest = get(&store, "docs/notes.md", &dir).unwrap();
+        assert_eq!(dest, dir.join("notes.md"));
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "hello");
+        assert!(store.list(Path::new("docs")).unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_taken_names_and_occupied_destinations() {
+        let dir = scratch("refuses");
+        let store = MemStore::default();
+        fs::write(dir.join("notes.md"), "first").unwrap();
+        put(&store, &dir.join("notes.md"), "").unwrap();
+
+        fs::write(dir.join("notes.md"), "second").unwrap();
+        assert!(put(&store, &dir.join("notes.md"), "").is_err());
+        assert!(get(&store, "notes.md", &dir).is_err());
+        assert_eq!(fs::read_to_string(dir.join("notes.md")).unwrap(), "second");
+        assert!(get(&store, "missing.md", &dir).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_paths_outside_of_the_slot() {
+        let dir = scratch("outside");
+        let store = MemStore::default();
+        fs::write(dir.join("notes.md"), "hello").unwrap();
+
+        assert!(put(&store, &dir.join("notes.md"), "../docs").is_err());
+        assert!(put(&store, &dir.join("notes.md"), "/docs").is_err());
+        assert!(get(&store, "../notes.md", &dir).is_err());
+        assert!(dir.join("notes.md").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Points the config file at a Vento directory inside the scratch directory, which deduplicates what's taken
+    #[cfg(unix)]
+    #[test]
+    fn linked_items_keep_their_own_copy_when_deduplicating() {
+        let dir = scratch("linked-dedup");
+        let ventodir = dir.join("vento");
+        fs::create_dir_all(dir.join("config")).unwrap();
+        fs::write(
+            dir.join("config/vento.toml"),
+            format!(
+                "directory = \"{}\"\n[storage]\ndedup = true\n",
+                ventodir.display()
+            ),
+        )
+        .unwrap();
+        std::env::set_var("XDG_CONFIG_HOME", dir.join("config"));
+        for slot in ["active", "inactive", template::TEMPLATES] {
+            fs::create_dir_all(ventodir.join(slot)).unwrap();
+        }
+
+        fs::write(dir.join("b.txt"), "same").unwrap();
+        fs::write(dir.join("a.txt"), "same").unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        take(&path("b.txt"), "inactive", false, false, true, false).unwrap();
+        take(&path("a.txt"), "active", false, false, true, true).unwrap();
+        dedup::dedup(false).unwrap();
+
+        // Writing through the link changes the linked item, but nothing else
+        fs::write(dir.join("a.txt"), "edited").unwrap();
+        assert_eq!(
+            fs::read_to_string(ventodir.join("active/a.txt")).unwrap(),
+            "edited"
+        );
+        assert_eq!(
+            fs::read_to_string(ventodir.join("inactive/b.txt")).unwrap(),
+            "same"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
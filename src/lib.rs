@@ -19,7 +19,17 @@
 
 pub mod archive;
 pub mod common;
+pub mod dedup;
+pub mod doctor;
+pub mod find;
+pub mod format;
 pub mod history;
 pub mod inv;
 pub mod item;
 pub mod message;
+pub mod meta;
+pub mod progress;
+pub mod snapshot;
+pub mod store;
+pub mod template;
+pub mod trash;
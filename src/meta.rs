@@ -0,0 +1,340 @@
+/*
+ * Vento, a CLI inventory for your files.
+ * Copyright (C) 2023 Lux Aliaga
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::{
+    common,
+    message::{append_emoji, EmojiType},
+    store,
+};
+use anyhow::{bail, Result};
+use colored::Colorize;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Items which were dropped keep their metadata under this slot, by the path they were dropped into
+const DROPPED: &str = "dropped";
+
+/// Tags and a note attached to an item
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// When the item expires, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<i64>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.note.is_none() && self.expires.is_none()
+    }
+
+    /// Whether the item has expired by now
+    pub fn expired(&self) -> bool {
+        self.expires.is_some_and(|expires| expires <= now())
+    }
+
+    /// Adds tags, leaving out the ones the item already has
+    pub fn add_tags(&mut self, tags: &[String]) -> Result<()> {
+        for tag in tags {
+            let tag = tag.trim();
+            if tag.is_empty() || tag.contains(',') || tag.contains(char::is_whitespace) {
+                bail!(
+                    "{}",
+                    format!(
+                        "Invalid tag \"{}\". Tags can't be empty, nor contain commas or spaces",
+                        tag
+                    )
+                    .red()
+                );
+            }
+            if !self.tags.iter().any(|existing| existing == tag) {
+                self.tags.push(String::from(tag));
+            }
+        }
+        Ok(())
+    }
+
+    /// Describes the metadata the way listings display it, after the item
+    pub fn describe(&self) -> String {
+        let mut description = String::new();
+        for tag in &self.tags {
+            description.push_str(&format!(" {}", format!("#{}", tag).cyan()));
+        }
+        if let Some(note) = &self.note {
+            description.push_str(&format!(" {}", format!("\"{}\"", note).italic()));
+        }
+        if let Some(expires) = self.expires {
+            description.push_str(&format!(
+                " {}",
+                match expires - now() {
+                    left if left > 0 =>
+                        format!("expires in {}", common::format_duration(left as u64)).dimmed(),
+                    _ => "(expired)".red(),
+                }
+            ));
+        }
+        description
+    }
+}
+
+/// Reads the metadata of an item in a slot
+pub fn get(slot: &str, file: &str) -> Result<Option<Metadata>> {
+    let db = open()?;
+    read(&db, slot_name(slot), file)
+}
+
+/// Sets the metadata of an item in a slot, removing it if it's empty
+pub fn set(slot: &str, file: &str, metadata: &Metadata) -> Result<()> {
+    let db = open()?;
+    write(&db, slot_name(slot), file, metadata)
+}
+
+/// Reads the metadata of every item in a slot
+pub fn slot(slot: &str) -> Result<BTreeMap<String, Metadata>> {
+    let db = open()?;
+    let mut items = BTreeMap::new();
+
+    let mut transaction =
+        db.prepare("SELECT file, tags, note, expires FROM metadata WHERE slot = ?1")?;
+    let rows = transaction.query_map([slot_name(slot)], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            parse(row.get(1)?, row.get(2)?, row.get(3)?),
+        ))
+    })?;
+    for row in rows {
+        let (file, metadata) = row?;
+        items.insert(file, metadata);
+    }
+
+    Ok(items)
+}
+
+/// Moves the metadata of every item along with them when slots are switched
+pub fn switch() -> Result<()> {
+    let db = open()?;
+    db.execute(
+        "UPDATE metadata SET slot = CASE slot WHEN 'active' THEN 'inactive' ELSE 'active' END
+            WHERE slot IN ('active', 'inactive')",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Keeps the metadata of an item being dropped, so it comes back if the item is taken again
+pub fn dropped(slot: &str, file: &str, dest: &Path) -> Result<()> {
+    let db = open()?;
+    let dest = fs::canonicalize(dest)?;
+
+    if let Some(metadata) = read(&db, slot_name(slot), file)? {
+        write(&db, slot_name(slot), file, &Metadata::default())?;
+        write(&db, DROPPED, &dest.to_string_lossy(), &metadata)?;
+    }
+    Ok(())
+}
+
+/// Brings back the metadata an item had when it was dropped, now that it was taken again
+pub fn taken(source: &Path, slot: &str, file: &str) -> Result<()> {
+    let db = open()?;
+    let source = source.to_string_lossy();
+
+    if let Some(metadata) = read(&db, DROPPED, &source)? {
+        write(&db, DROPPED, &source, &Metadata::default())?;
+        write(&db, slot_name(slot), file, &metadata)?;
+    }
+    Ok(())
+}
+
+/// Forgets the metadata of dropped items which were inside a directory that's gone
+pub fn forget_dropped(dir: &Path) -> Result<()> {
+    let db = open()?;
+    db.execute(
+        "DELETE FROM metadata WHERE slot = ?1 AND (file = ?2 OR file LIKE ?3)",
+        (
+            DROPPED,
+            dir.to_string_lossy(),
+            format!("{}/%", dir.to_string_lossy()),
+        ),
+    )?;
+    Ok(())
+}
+
+/// Finds how long items taken into a slot last by default, from the config file
+pub fn default_ttl(slot: &str) -> Result<Option<u64>> {
+    let config = common::parse_config()?;
+    Ok(match slot_name(slot) {
+        "active" => config.ttl_active,
+        "inactive" => config.ttl_inactive,
+        _ => None,
+    })
+}
+
+/// Sets an item to expire after some amount of seconds, or never if none is given
+pub fn expire(slot: &str, file: &str, ttl: Option<u64>) -> Result<()> {
+    let mut metadata = get(slot, file)?.unwrap_or_default();
+    metadata.expires = ttl.map(|ttl| now() + ttl as i64);
+    set(slot, file, &metadata)
+}
+
+/// Edits the tags and note of an item in a slot
+pub fn edit(
+    file: &str,
+    slot: &str,
+    tags: &[String],
+    untags: &[String],
+    note: Option<String>,
+    ttl: Option<Option<u64>>,
+) -> Result<()> {
+    // Items inside directories in the slot can be edited too, as long as they stay inside of it
+    store::check_path(Path::new(file))?;
+    if file.is_empty() || !store::open(slot)?.exists(Path::new(file))? {
+        bail!(
+            "{}",
+            format!(
+                "No item named {} in the {} slot",
+                file.bold(),
+                slot_name(slot)
+            )
+            .red()
+        );
+    }
+
+    let mut metadata = get(slot, file)?.unwrap_or_default();
+    metadata.add_tags(tags)?;
+    metadata.tags.retain(|tag| !untags.contains(tag));
+    if let Some(note) = note {
+        // An empty note clears it
+        metadata.note = Some(note).filter(|note| !note.is_empty());
+    }
+    if let Some(ttl) = ttl {
+        metadata.expires = ttl.map(|ttl| now() + ttl as i64);
+    }
+    set(slot, file, &metadata)?;
+
+    println!(
+        "{}{} {}{}",
+        append_emoji(EmojiType::Success)?,
+        "Updated".green(),
+        file.bold(),
+        metadata.describe()
+    );
+    Ok(())
+}
+
+/// Forgets the metadata of every item in the slots, keeping the metadata of dropped items
+pub fn forget_slots(db: &Connection) -> Result<()> {
+    table(db)?;
+    db.execute(
+        "DELETE FROM metadata WHERE slot IN ('active', 'inactive', 'templates')",
+        (),
+    )?;
+    Ok(())
+}
+
+fn open() -> Result<Connection> {
+    let db = Connection::open(common::env_config()?.vento_dir.join("history.db3"))?;
+    table(&db)?;
+    Ok(db)
+}
+
+/// Creates the table metadata is kept in, if the database doesn't have it yet
+pub fn table(db: &Connection) -> Result<()> {
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS metadata (
+                slot    TEXT NOT NULL,
+                file    TEXT NOT NULL,
+                tags    TEXT NOT NULL,
+                note    TEXT,
+                expires INTEGER,
+                PRIMARY KEY (slot, file))",
+        (),
+    )?;
+
+    // Expiry came after the table did, so older databases don't have it yet
+    let expires: i64 = db.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('metadata') WHERE name = 'expires'",
+        [],
+        |row| row.get(0),
+    )?;
+    if expires == 0 {
+        db.execute("ALTER TABLE metadata ADD COLUMN expires INTEGER", ())?;
+    }
+    Ok(())
+}
+
+fn read(db: &Connection, slot: &str, file: &str) -> Result<Option<Metadata>> {
+    Ok(db
+        .query_row(
+            "SELECT tags, note, expires FROM metadata WHERE slot = ?1 AND file = ?2",
+            [slot, file],
+            |row| Ok(parse(row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?)
+}
+
+fn write(db: &Connection, slot: &str, file: &str, metadata: &Metadata) -> Result<()> {
+    match metadata.is_empty() {
+        true => db.execute(
+            "DELETE FROM metadata WHERE slot = ?1 AND file = ?2",
+            [slot, file],
+        )?,
+        false => db.execute(
+            "INSERT OR REPLACE INTO metadata (slot, file, tags, note, expires) VALUES (?1, ?2, ?3, ?4, ?5)",
+            (slot, file, metadata.tags.join(","), &metadata.note, metadata.expires),
+        )?,
+    };
+    Ok(())
+}
+
+fn parse(tags: String, note: Option<String>, expires: Option<i64>) -> Metadata {
+    Metadata {
+        tags: tags
+            .split(',')
+            .filter(|tag| !tag.is_empty())
+            .map(String::from)
+            .collect(),
+        note,
+        expires,
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+fn slot_name(slot: &str) -> &str {
+    match slot {
+        "a" | "active" => "active",
+        "i" | "inactive" => "inactive",
+        "t" | "templates" => "templates",
+        _ => slot,
+    }
+}
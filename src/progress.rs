@@ -0,0 +1,322 @@
+/*
+ * Vento, a CLI inventory for your files.
+ * Copyright (C) 2023 Lux Aliaga
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::common;
+use anyhow::{bail, Result};
+use colored::Colorize;
+use serde_json::json;
+use size_format::SizeFormatterBinary;
+use std::{
+    fs::{self, File},
+    io::{self, IsTerminal, Read, Write},
+    path::Path,
+    str::FromStr,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+/// How progress is reported while moving or archiving files
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// A progress bar when the standard error is a terminal, and nothing otherwise
+    Auto,
+    /// A progress bar, even when the standard error isn't a terminal
+    Bar,
+    /// A line of JSON for every update, for other programs to follow along
+    Json,
+    /// Nothing at all
+    Quiet,
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> Result<Self> {
+        match mode {
+            "auto" => Ok(Mode::Auto),
+            "bar" => Ok(Mode::Bar),
+            "json" => Ok(Mode::Json),
+            "quiet" => Ok(Mode::Quiet),
+            _ => bail!(
+                "{}",
+                format!(
+                    "No such progress mode. Valid modes are {}, {}, {} and {}",
+                    "auto".bold(),
+                    "bar".bold(),
+                    "json".bold(),
+                    "quiet".bold()
+                )
+                .red()
+            ),
+        }
+    }
+}
+
+static MODE: OnceLock<Mode> = OnceLock::new();
+
+/// Sets how progress is reported for the rest of the program, overriding progress.mode in the config file
+pub fn set_mode(mode: Mode) {
+    let _ = MODE.set(mode);
+}
+
+// Finds how progress is reported, only showing a bar if there's a terminal to show it in
+fn mode() -> Mode {
+    let mode = *MODE.get_or_init(|| {
+        common::parse_config()
+            .ok()
+            .and_then(|config| config.progress_mode.parse().ok())
+            .unwrap_or(Mode::Auto)
+    });
+
+    match mode {
+        Mode::Auto if io::stderr().is_terminal() => Mode::Bar,
+        Mode::Auto => Mode::Quiet,
+        mode => mode,
+    }
+}
+
+// Bars only show up for tasks which take a while, so quick ones don't flicker
+const DELAY: Duration = Duration::from_millis(500);
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Reports how far along a task moving files around is, into the standard error
+pub struct Progress {
+    task: String,
+    mode: Mode,
+    files: u64,
+    bytes: u64,
+    total_files: Option<u64>,
+    total_bytes: Option<u64>,
+    started: Instant,
+    reported: Option<Instant>,
+    finished: bool,
+}
+
+impl Progress {
+    /// Starts reporting a task, along with how many files and bytes it will go through if it's known
+    pub fn new(task: &str, total_files: Option<u64>, total_bytes: Option<u64>) -> Progress {
+        Progress {
+            task: String::from(task),
+            mode: mode(),
+            files: 0,
+            bytes: 0,
+            total_files,
+            total_bytes,
+            started: Instant::now(),
+            reported: None,
+            finished: false,
+        }
+    }
+
+    /// Starts reporting a task which goes through everything inside a file or directory
+    pub fn measure(task: &str, path: &Path) -> Progress {
+        let (files, bytes) = measure(path);
+        Progress::new(task, Some(files), Some(bytes))
+    }
+
+    /// Counts some bytes as done
+    pub fn add_bytes(&mut self, bytes: u64) {
+        self.bytes += bytes;
+        self.report(false);
+    }
+
+    /// Counts a file as done
+    pub fn add_file(&mut self) {
+        self.files += 1;
+        self.report(false);
+    }
+
+    /// Sets how many bytes are done, for tasks which keep track of it themselves
+    pub fn set_bytes(&mut self, bytes: u64) {
+        self.bytes = bytes;
+        self.report(false);
+    }
+
+    /// Wraps a reader, counting every byte read through it as done
+    pub fn reader<R: Read>(&mut self, inner: R) -> Reading<'_, R> {
+        Reading {
+            inner,
+            progress: self,
+        }
+    }
+
+    /// Reports the task as done, clearing its bar
+    pub fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+
+        match self.mode {
+            Mode::Bar if self.reported.is_some() => eprint!("\r\x1b[2K"),
+            Mode::Json => self.report(true),
+            _ => (),
+        }
+    }
+
+    // Reports how far along the task is, at most once every interval unless forced to
+    fn report(&mut self, force: bool) {
+        let now = Instant::now();
+        let due = match self.reported {
+            Some(reported) => now - reported >= INTERVAL,
+            None => self.mode == Mode::Json || now - self.started >= DELAY,
+        };
+        if !force && !due {
+            return;
+        }
+        self.reported = Some(now);
+
+        match self.mode {
+            Mode::Bar => {
+                eprint!("\r\x1b[2K{}", self.bar());
+                let _ = io::stderr().flush();
+            }
+            Mode::Json => eprintln!("{}", self.json()),
+            _ => (),
+        }
+    }
+
+    // How many bytes are done every second, and how long it should take to finish if the total is known
+    fn rate(&self) -> (u64, Option<u64>) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = match elapsed > 0.0 {
+            true => (self.bytes as f64 / elapsed) as u64,
+            false => 0,
+        };
+        let eta = match (self.total_bytes, rate) {
+            (Some(total), 1..) => Some(total.saturating_sub(self.bytes) / rate),
+            _ => None,
+        };
+        (rate, eta)
+    }
+
+    fn bar(&self) -> String {
+        let (rate, eta) = self.rate();
+        let mut details = format!("{}B", SizeFormatterBinary::new(self.bytes));
+        if let Some(total) = self.total_bytes {
+            details.push_str(&format!("/{}B", SizeFormatterBinary::new(total)));
+        }
+        details.push_str(&format!(", {}", self.files));
+        if let Some(total) = self.total_files {
+            details.push_str(&format!("/{}", total));
+        }
+        details.push_str(&format!(" files, {}B/s", SizeFormatterBinary::new(rate)));
+        if let Some(eta) = eta {
+            details.push_str(&format!(", {} left", common::format_duration(eta)));
+        }
+
+        // The bar takes up whatever room is left in the terminal
+        let columns = termion::terminal_size()
+            .map(|(columns, _)| columns as usize)
+            .unwrap_or(80);
+        let room = columns.saturating_sub(self.task.len() + details.len() + 10);
+        match self.total_bytes {
+            Some(total) if room >= 10 => {
+                let ratio = match total {
+                    0 => 1.0,
+                    _ => (self.bytes as f64 / total as f64).min(1.0),
+                };
+                let width = room.min(40);
+                let filled = (ratio * width as f64) as usize;
+                format!(
+                    "{} [{}{}] {:>3}% {}",
+                    self.task.green(),
+                    "#".repeat(filled),
+                    "-".repeat(width - filled),
+                    (ratio * 100.0) as u64,
+                    details.dimmed()
+                )
+            }
+            _ => format!("{} {}", self.task.green(), details.dimmed()),
+        }
+    }
+
+    fn json(&self) -> String {
+        let (rate, eta) = self.rate();
+        json!({
+            "task": self.task,
+            "files": self.files,
+            "total_files": self.total_files,
+            "bytes": self.bytes,
+            "total_bytes": self.total_bytes,
+            "rate": rate,
+            "eta": eta,
+            "done": self.finished,
+        })
+        .to_string()
+    }
+}
+
+impl Drop for Progress {
+    // Tasks which fail midway still leave the terminal clean
+    fn drop(&mut self) {
+        if self.mode == Mode::Bar && !self.finished && self.reported.is_some() {
+            eprint!("\r\x1b[2K");
+        }
+    }
+}
+
+/// A reader counting every byte read through it towards some progress
+pub struct Reading<'a, R: Read> {
+    inner: R,
+    progress: &'a mut Progress,
+}
+
+impl<R: Read> Read for Reading<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.progress.add_bytes(read as u64);
+        Ok(read)
+    }
+}
+
+/// Copies a file, following symlinks, keeping its permissions and reporting how much was copied
+pub fn copy(source: &Path, dest: &Path, progress: &mut Progress) -> Result<()> {
+    // With nothing to report along the way, the standard library is left to copy it, which lets the kernel do it
+    if progress.mode == Mode::Quiet {
+        let copied = fs::copy(source, dest)?;
+        progress.add_bytes(copied);
+        progress.add_file();
+        return Ok(());
+    }
+
+    let metadata = fs::metadata(source)?;
+    let mut output = File::create(dest)?;
+    io::copy(&mut progress.reader(File::open(source)?), &mut output)?;
+    output.set_permissions(metadata.permissions())?;
+    progress.add_file();
+    Ok(())
+}
+
+/// Counts the files inside a file or directory, along with how big they are
+pub fn measure(path: &Path) -> (u64, u64) {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return (0, 0);
+    };
+    if !metadata.is_dir() {
+        return (1, metadata.len());
+    }
+
+    let mut total = (0, 0);
+    for item in fs::read_dir(path).into_iter().flatten().flatten() {
+        let (files, bytes) = measure(&item.path());
+        total = (total.0 + files, total.1 + bytes);
+    }
+    total
+}
@@ -0,0 +1,315 @@
+/*
+ * Vento, a CLI inventory for your files.
+ * Copyright (C) 2023 Lux Aliaga
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::{
+    archive, common,
+    format::{Compression, Format},
+    message::{append_emoji, EmojiType},
+};
+use anyhow::{bail, Result};
+use chrono::{Datelike, Local, NaiveDateTime};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// The name of the index of past exports inside the Vento directory
+pub const INDEX: &str = ".vento-snapshots";
+
+/// What an export of the Vento directory contains
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// Every file in the directory
+    Full,
+    /// Files changed since the last export of any kind
+    Incremental,
+    /// Files changed since the last full export
+    Differential,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Kind::Full => "full",
+                Kind::Incremental => "incremental",
+                Kind::Differential => "differential",
+            }
+        )
+    }
+}
+
+/// The state of a file when it was exported, used to tell if it changed since
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileState {
+    pub size: u64,
+    pub mtime: u64,
+}
+
+/// A past export of the Vento directory
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: String,
+    pub kind: Kind,
+    pub base: Option<String>,
+    pub time: i64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, FileState>,
+}
+
+/// Every export of the Vento directory made so far, oldest first
+#[derive(Default, Serialize, Deserialize)]
+pub struct Index {
+    pub snapshots: Vec<Snapshot>,
+}
+
+impl Index {
+    /// Reads the index from the Vento directory, or starts an empty one if there's none
+    pub fn load() -> Result<Index> {
+        let path = index_path()?;
+
+        match path.is_file() {
+            true => Ok(serde_json::from_slice(&fs::read(&path)?)?),
+            false => Ok(Index::default()),
+        }
+    }
+
+    /// Writes the index into the Vento directory
+    pub fn save(&mut self) -> Result<()> {
+        self.save_into(&common::env_config()?.vento_dir)
+    }
+
+    /// Writes the index into a directory, such as one an export was restored into
+    pub fn save_into(&mut self, dir: &Path) -> Result<()> {
+        // Only the latest export and the latest full one can be built upon, so older file lists are dropped
+        let latest = self.snapshots.len().checked_sub(1);
+        let full = self.snapshots.iter().rposition(|s| s.kind == Kind::Full);
+        for (index, snapshot) in self.snapshots.iter_mut().enumerate() {
+            if Some(index) != latest && Some(index) != full {
+                snapshot.files.clear();
+            }
+        }
+
+        let path = dir.join(INDEX);
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&temp, &path)?;
+        Ok(())
+    }
+
+    /// Finds the export a new one of some kind is based on
+    pub fn base(&self, kind: Kind) -> Result<Option<&Snapshot>> {
+        let base = match kind {
+            Kind::Full => return Ok(None),
+            Kind::Incremental => self.snapshots.last(),
+            Kind::Differential => self.snapshots.iter().rev().find(|s| s.kind == Kind::Full),
+        };
+
+        match base {
+            Some(base) => Ok(Some(base)),
+            None => bail!(
+                "{}",
+                "There's no previous export to base this one on. Export the whole Vento directory first"
+                    .red()
+            ),
+        }
+    }
+
+    /// Comes up with an identifier for a new export, based on the current time
+    pub fn next_id(&self) -> String {
+        let time = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let mut id = time.clone();
+        let mut count = 1;
+
+        while self.snapshots.iter().any(|s| s.id == id) {
+            count += 1;
+            id = format!("{}-{}", time, count);
+        }
+        id
+    }
+}
+
+/// Records the size and modification time of every file inside a directory, skipping the names excluded
+pub fn state(
+    dir: &Path,
+    prefix: &str,
+    exclude: &[&str],
+    files: &mut BTreeMap<String, FileState>,
+) -> Result<()> {
+    for item in fs::read_dir(dir)? {
+        let item = item?;
+        let name = item.file_name();
+        if exclude.iter().any(|excluded| name == *excluded) {
+            continue;
+        }
+
+        let path = match prefix {
+            "" => name.to_string_lossy().to_string(),
+            _ => format!("{}/{}", prefix, name.to_string_lossy()),
+        };
+        let metadata = fs::symlink_metadata(item.path())?;
+
+        if metadata.is_dir() {
+            state(&item.path(), &path, &[], files)?;
+        } else {
+            let mtime = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as u64;
+            files.insert(
+                path,
+                FileState {
+                    size: metadata.len(),
+                    mtime,
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+fn index_path() -> Result<PathBuf> {
+    Ok(common::env_config()?.vento_dir.join(INDEX))
+}
+
+/// Exports the Vento directory into a timestamped archive inside the snapshot directory, then prunes older snapshots
+pub fn take(message: bool) -> Result<()> {
+    let config = common::parse_config()?;
+    let dir = snapshot_dir()?;
+    let vento_dir = common::env_config()?.vento_dir;
+
+    if !vento_dir.is_dir() {
+        bail!(
+            "{}",
+            "Vento not initialized, so there's nothing to snapshot".red()
+        );
+    }
+    if dir.starts_with(&vento_dir) {
+        bail!(
+            "{}",
+            "The snapshot directory can't be inside the Vento directory".red()
+        );
+    }
+    fs::create_dir_all(&dir)?;
+
+    let format: Format = config.archive_format.parse()?;
+    let stamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut output = dir.join(format!("vento-{}.{}", stamp, format.extension()));
+    let mut count = 1;
+    while output.exists() {
+        count += 1;
+        output = dir.join(format!("vento-{}-{}.{}", stamp, count, format.extension()));
+    }
+
+    let compression = Compression::new(Some(format), &output, None, None, None)?;
+    if let Err(error) = archive::export_dir(output.clone(), &compression, Kind::Full, false, false)
+    {
+        let _ = fs::remove_file(&output);
+        return Err(error);
+    }
+
+    if message {
+        println!(
+            "{}{} {}",
+            append_emoji(EmojiType::Success)?,
+            "Saved snapshot".green(),
+            output.display()
+        );
+    }
+
+    prune(&dir, message)
+}
+
+/// Takes a snapshot before a destructive operation, if automatic snapshots are enabled
+pub fn auto(message: bool) -> Result<()> {
+    if common::parse_config()?.snapshot_auto && common::env_config()?.vento_dir.is_dir() {
+        take(message)?;
+    }
+    Ok(())
+}
+
+/// Removes the snapshots which the retention policy doesn't keep
+pub fn prune(dir: &Path, message: bool) -> Result<()> {
+    let config = common::parse_config()?;
+
+    let mut snapshots: Vec<(NaiveDateTime, PathBuf)> = vec![];
+    for item in fs::read_dir(dir)? {
+        let path = item?.path();
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        if let Some(stamp) = name.strip_prefix("vento-").and_then(|name| name.get(..15)) {
+            if let Ok(time) = NaiveDateTime::parse_from_str(stamp, "%Y%m%d-%H%M%S") {
+                snapshots.push((time, path));
+            }
+        }
+    }
+    snapshots.sort_by(|a, b| b.cmp(a));
+
+    // The newest snapshots are kept, along with the newest one of each recent day and week
+    let today = Local::now().date_naive();
+    let mut days = BTreeSet::new();
+    let mut weeks = BTreeSet::new();
+    let mut removed = 0;
+
+    for (index, (time, path)) in snapshots.iter().enumerate() {
+        let age = (today - time.date()).num_days();
+        let week = (time.iso_week().year(), time.iso_week().week());
+
+        let last = index == 0 || index < config.snapshot_keep_last;
+        let daily = age < config.snapshot_keep_daily as i64 && days.insert(time.date());
+        let weekly = age < config.snapshot_keep_weekly as i64 * 7 && weeks.insert(week);
+
+        if !(last || daily || weekly) {
+            fs::remove_file(path)?;
+            removed += 1;
+        }
+    }
+
+    if message && removed > 0 {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Inventory)?,
+            format!("Pruned {} old snapshots", removed).green()
+        );
+    }
+    Ok(())
+}
+
+/// Finds where snapshots are kept, which is the data directory unless set in the config file
+fn snapshot_dir() -> Result<PathBuf> {
+    if let Some(dir) = common::parse_config()?.snapshot_dir {
+        return Ok(dir);
+    }
+
+    match dirs::data_dir() {
+        Some(dir) => Ok(dir.join("vento").join("snapshots")),
+        None => Ok(common::env_config()?.vento_dir.with_extension("snapshots")),
+    }
+}
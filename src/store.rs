@@ -0,0 +1,634 @@
+/*
+ * Vento, a CLI inventory for your files.
+ * Copyright (C) 2023 Lux Aliaga
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::{
+    common, dedup,
+    inv::ItemKind,
+    message::{throw_error, ErrorType},
+    progress::{self, Progress},
+};
+use anyhow::{bail, Result};
+use colored::Colorize;
+use fs_extra::dir::{move_dir_with_progress, CopyOptions, TransitProcessResult};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+/// An item inside a slot, or inside a directory in it
+pub struct Entry {
+    pub name: String,
+    pub kind: ItemKind,
+    /// The size of files, or 0 for anything else
+    pub size: u64,
+    /// How much space files take up where they're stored, which is less than their size if they're compressed
+    pub stored_size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// Where the items in a slot are kept. Paths given to it are relative to the slot
+pub trait SlotStore {
+    /// Moves a file or directory from outside of Vento into the slot, under a name
+    fn put(&self, source: &Path, name: &str) -> Result<()>;
+
+    /// Moves an item out of the slot into a destination, which is the path it ends up at
+    fn get(&self, name: &str, dest: &Path) -> Result<()>;
+
+    /// Lists the items inside a directory in the slot, or the slot itself if the path is empty
+    fn list(&self, dir: &Path) -> Result<Vec<Entry>>;
+
+    /// Removes an item from the slot for good
+    fn remove(&self, name: &str) -> Result<()>;
+
+    /// Renames an item inside the slot
+    fn rename(&self, from: &str, to: &str) -> Result<()>;
+
+    /// Describes an item inside the slot, if there's one at that path
+    fn metadata(&self, path: &Path) -> Result<Option<Entry>>;
+
+    /// Where the slot lives on disk, for operations which work on the files themselves such as archiving
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Whether there's an item at a path inside the slot
+    fn exists(&self, path: &Path) -> Result<bool> {
+        Ok(self.metadata(path)?.is_some())
+    }
+}
+
+/// Opens the store of a slot
+pub fn open(slot: &str) -> Result<Box<dyn SlotStore>> {
+    let dir = match slot {
+        "active" | "a" => common::env_config()?.active_dir,
+        "inactive" | "i" => common::env_config()?.inactive_dir,
+        "templates" | "t" => {
+            // Vento directories made before templates existed don't have one until it's used
+            let dir = common::env_config()?.templates_dir;
+            if common::env_config()?.vento_dir.is_dir() {
+                fs::create_dir_all(&dir)?;
+            }
+            dir
+        }
+        _ => bail!(
+            "{}",
+            format!(
+                "No such slot. Valid slots are {}, {} and {}",
+                "active".green().bold(),
+                "inactive".blue().bold(),
+                "templates".magenta().bold()
+            )
+            .red()
+        ),
+    };
+
+    Ok(Box::new(DirStore {
+        dir,
+        compress: compressed(slot)?.then_some(common::parse_config()?.compress_level),
+    }))
+}
+
+/// Whether files put into a slot are compressed, according to the config file
+pub fn compressed(slot: &str) -> Result<bool> {
+    let config = common::parse_config()?;
+    Ok(match slot {
+        "active" | "a" => config.compress_active,
+        "inactive" | "i" => config.compress_inactive,
+        _ => false,
+    })
+}
+
+/// Splits a location such as active:docs/notes.md into the full name of its slot and the path inside of it, if it
+/// starts with a slot
+pub fn parse_location(location: &str) -> Option<(&str, &str)> {
+    let (slot, path) = location.split_once(':')?;
+    let slot = match slot {
+        "active" | "a" => "active",
+        "inactive" | "i" => "inactive",
+        "templates" | "t" => "templates",
+        _ => return None,
+    };
+    Some((slot, path))
+}
+
+/// Makes sure a path inside a slot can't reach out of it, through its parent or by being absolute
+pub fn check_path(path: &Path) -> Result<()> {
+    if path
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        throw_error(ErrorType::NoAccessParent)?;
+    }
+    Ok(())
+}
+
+/// Opens the store of a slot, requiring it to live on disk
+pub fn open_dir(slot: &str) -> Result<PathBuf> {
+    match open(slot)?.path() {
+        Some(dir) => Ok(dir.to_path_buf()),
+        None => bail!(
+            "{}",
+            "This can only be done with slots stored in a directory".red()
+        ),
+    }
+}
+
+/// Keeps a slot's items inside a directory, optionally compressing the files inside of them
+pub struct DirStore {
+    pub dir: PathBuf,
+    /// The level files are compressed at when put into the slot, if they're compressed at all.
+    /// Compressed files are read no matter what, since switching slots moves them into the other slot
+    pub compress: Option<i32>,
+}
+
+impl DirStore {
+    // Joins a path to the slot's directory, refusing paths that would reach out of it
+    fn join(&self, path: &Path) -> Result<PathBuf> {
+        check_path(path)?;
+        Ok(self.dir.join(path))
+    }
+
+    // Finds where an item is on disk, which has the compressed extension if it's a compressed file
+    fn locate(&self, path: &Path) -> Result<Option<PathBuf>> {
+        let full = self.join(path)?;
+        if fs::symlink_metadata(&full).is_ok() {
+            return Ok(Some(full));
+        }
+
+        let compressed = compressed_path(&full);
+        Ok(is_compressed(&compressed).then_some(compressed))
+    }
+}
+
+impl SlotStore for DirStore {
+    fn put(&self, source: &Path, name: &str) -> Result<()> {
+        if !self.dir.is_dir() {
+            throw_error(ErrorType::NoFileOrDir)?;
+        }
+        let dest = self.join(Path::new(name))?;
+        fs::create_dir_all(dest.parent().unwrap_or(&self.dir))?;
+
+        // Symlinks are taken as the file they point to
+        if source.is_file() {
+            let mut progress = Progress::measure("Taking", source);
+            progress::copy(source, &dest, &mut progress)?;
+            progress.finish();
+            fs::remove_file(source)?;
+        } else if source.is_dir() {
+            move_item(source, &dest, "Taking")?;
+        } else {
+            throw_error(ErrorType::NoFileOrDir)?;
+        }
+
+        if let Some(level) = self.compress {
+            compress(&dest, level)?;
+        }
+        Ok(())
+    }
+
+    fn get(&self, name: &str, dest: &Path) -> Result<()> {
+        let Some(source) = self.locate(Path::new(name))? else {
+            return throw_error(ErrorType::NoFileOrDir);
+        };
+
+        if is_compressed(&source) {
+            decompress_file(&source, dest)?;
+        } else if source.is_file() || source.is_symlink() {
+            let mut progress = Progress::measure("Dropping", &source);
+            progress::copy(&source, dest, &mut progress)?;
+            progress.finish();
+            fs::remove_file(&source)?;
+        } else {
+            // Files are copied out, but directories are moved along with whatever objects their files share
+            dedup::release(&source)?;
+            move_item(&source, dest, "Dropping")?;
+            decompress(dest)?;
+        }
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<Entry>> {
+        let mut entries = vec![];
+        for item in fs::read_dir(self.join(dir)?)? {
+            entries.push(entry(&item?.path())?);
+        }
+        Ok(entries)
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        let Some(path) = self.locate(Path::new(name))? else {
+            return throw_error(ErrorType::NoFileOrDir);
+        };
+        match fs::symlink_metadata(&path)?.is_dir() {
+            true => fs::remove_dir_all(&path)?,
+            false => fs::remove_file(&path)?,
+        }
+        Ok(())
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let Some(source) = self.locate(Path::new(from))? else {
+            return throw_error(ErrorType::NoFileOrDir);
+        };
+        let dest = self.join(Path::new(to))?;
+        match is_compressed(&source) {
+            true => fs::rename(&source, compressed_path(&dest))?,
+            false => fs::rename(&source, &dest)?,
+        }
+        Ok(())
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Option<Entry>> {
+        match self.locate(path)? {
+            Some(full) => Ok(Some(entry(&full)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.dir)
+    }
+}
+
+/// Keeps a slot's items in memory, so the library can be used without touching the Vento directory
+#[derive(Default)]
+pub struct MemStore {
+    items: RefCell<BTreeMap<String, MemItem>>,
+}
+
+// An item kept in memory, along with everything inside of it
+enum MemItem {
+    File(Vec<u8>, SystemTime),
+    Directory(BTreeMap<String, MemItem>, SystemTime),
+}
+
+impl MemItem {
+    fn read(path: &Path) -> Result<MemItem> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified().unwrap_or(SystemTime::now());
+        match metadata.is_dir() {
+            true => {
+                let mut children = BTreeMap::new();
+                for item in fs::read_dir(path)? {
+                    let item = item?;
+                    children.insert(
+                        item.file_name().to_string_lossy().to_string(),
+                        MemItem::read(&item.path())?,
+                    );
+                }
+                Ok(MemItem::Directory(children, modified))
+            }
+            false => Ok(MemItem::File(fs::read(path)?, modified)),
+        }
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        match self {
+            MemItem::File(contents, _) => fs::write(path, contents)?,
+            MemItem::Directory(children, _) => {
+                fs::create_dir(path)?;
+                for (name, child) in children {
+                    child.write(&path.join(name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn entry(&self, name: &str) -> Entry {
+        match self {
+            MemItem::File(contents, modified) => Entry {
+                name: String::from(name),
+                kind: ItemKind::File,
+                size: contents.len() as u64,
+                stored_size: contents.len() as u64,
+                modified: Some(*modified),
+            },
+            MemItem::Directory(_, modified) => Entry {
+                name: String::from(name),
+                kind: ItemKind::Directory,
+                size: 0,
+                stored_size: 0,
+                modified: Some(*modified),
+            },
+        }
+    }
+}
+
+// The name an item is kept under inside the directory it's in
+fn mem_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+// Finds the items inside the directory a path is in, if there's such a directory
+fn mem_parent<'a>(
+    items: &'a mut BTreeMap<String, MemItem>,
+    path: &Path,
+) -> Option<&'a mut BTreeMap<String, MemItem>> {
+    let mut children = items;
+    for name in path.parent().into_iter().flatten() {
+        children = match children.get_mut(name.to_string_lossy().as_ref())? {
+            MemItem::Directory(children, _) => children,
+            MemItem::File(..) => return None,
+        };
+    }
+    Some(children)
+}
+
+impl MemStore {
+    // Runs something on the item at a path, if there's one
+    fn find<T>(&self, path: &Path, run: impl FnOnce(&str, &MemItem) -> T) -> Option<T> {
+        let items = self.items.borrow();
+        let mut names = path.iter().map(|name| name.to_string_lossy().to_string());
+        let first = names.next()?;
+        let mut name = first.clone();
+        let mut item = items.get(&first)?;
+
+        for next in names {
+            item = match item {
+                MemItem::Directory(children, _) => children.get(&next)?,
+                MemItem::File(..) => return None,
+            };
+            name = next;
+        }
+        Some(run(&name, item))
+    }
+}
+
+impl SlotStore for MemStore {
+    fn put(&self, source: &Path, name: &str) -> Result<()> {
+        let item = MemItem::read(source)?;
+        let mut items = self.items.borrow_mut();
+        let Some(children) = mem_parent(&mut items, Path::new(name)) else {
+            bail!("{}", "No such directory".red());
+        };
+
+        match fs::symlink_metadata(source)?.is_dir() {
+            true => fs::remove_dir_all(source)?,
+            false => fs::remove_file(source)?,
+        }
+        children.insert(mem_name(Path::new(name)), item);
+        Ok(())
+    }
+
+    fn get(&self, name: &str, dest: &Path) -> Result<()> {
+        match self.find(Path::new(name), |_, item| item.write(dest)) {
+            Some(written) => written?,
+            None => throw_error(ErrorType::NoFileOrDir)?,
+        }
+        self.remove(name)
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<Entry>> {
+        if dir.as_os_str().is_empty() {
+            return Ok(self
+                .items
+                .borrow()
+                .iter()
+                .map(|(name, item)| item.entry(name))
+                .collect());
+        }
+
+        match self.find(dir, |_, item| match item {
+            MemItem::Directory(children, _) => Some(
+                children
+                    .iter()
+                    .map(|(name, item)| item.entry(name))
+                    .collect(),
+            ),
+            MemItem::File(..) => None,
+        }) {
+            Some(Some(entries)) => Ok(entries),
+            _ => bail!("{}", "No such directory".red()),
+        }
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        let mut items = self.items.borrow_mut();
+        let removed = mem_parent(&mut items, Path::new(name))
+            .and_then(|children| children.remove(&mem_name(Path::new(name))));
+        if removed.is_none() {
+            throw_error(ErrorType::NoFileOrDir)?;
+        }
+        Ok(())
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let mut items = self.items.borrow_mut();
+        if Path::new(to).starts_with(from) {
+            bail!("{}", "An item can't be moved inside of itself".red());
+        }
+        // Checked before the item is taken out, so it's never left without a place to go
+        if mem_parent(&mut items, Path::new(to)).is_none() {
+            bail!("{}", "No such directory".red());
+        }
+        let Some(item) = mem_parent(&mut items, Path::new(from))
+            .and_then(|children| children.remove(&mem_name(Path::new(from))))
+        else {
+            return throw_error(ErrorType::NoFileOrDir);
+        };
+
+        if let Some(children) = mem_parent(&mut items, Path::new(to)) {
+            children.insert(mem_name(Path::new(to)), item);
+        }
+        Ok(())
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Option<Entry>> {
+        Ok(self.find(path, |name, item| item.entry(name)))
+    }
+}
+
+// Describes an item on disk, by the name and size it had before being compressed if it was
+fn entry(path: &Path) -> Result<Entry> {
+    let metadata = fs::symlink_metadata(path)?;
+    let kind = if metadata.is_dir() {
+        ItemKind::Directory
+    } else if metadata.is_symlink() {
+        ItemKind::Symlink
+    } else {
+        ItemKind::File
+    };
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let stored_size = match kind {
+        ItemKind::File => metadata.len(),
+        _ => 0,
+    };
+
+    let (name, size) = match is_compressed(path) {
+        true => (
+            String::from(name.strip_suffix(COMPRESSED).unwrap_or(&name)),
+            content_size(path)?,
+        ),
+        false => (name, stored_size),
+    };
+    Ok(Entry {
+        name,
+        kind,
+        size,
+        stored_size,
+        modified: metadata.modified().ok(),
+    })
+}
+
+/// The extension files compressed inside a slot have
+pub const COMPRESSED: &str = ".vento-zst";
+
+// Files compressed by Vento start with a skippable zstd frame marking them, which decoders pass over. Files which only
+// happen to have the extension don't, so they're kept as they are
+const MARK: [u8; 13] = [
+    0x56, 0x2a, 0x4d, 0x18, 5, 0, 0, 0, b'v', b'e', b'n', b't', b'o',
+];
+
+fn is_compressed(path: &Path) -> bool {
+    if !path.to_string_lossy().ends_with(COMPRESSED) || !path.is_file() || path.is_symlink() {
+        return false;
+    }
+
+    let mut mark = [0; MARK.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut mark))
+        .is_ok_and(|_| mark == MARK)
+}
+
+/// Where a file is stored once it's compressed
+pub fn compressed_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(COMPRESSED);
+    PathBuf::from(name)
+}
+
+// Compresses every file inside an item in place, keeping their permissions and modification times
+fn compress(path: &Path, level: i32) -> Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for item in fs::read_dir(path)? {
+            compress(&item?.path(), level)?;
+        }
+        return Ok(());
+    }
+    if !metadata.is_file() || is_compressed(path) {
+        return Ok(());
+    }
+
+    let dest = compressed_path(path);
+    let mut output = File::create(&dest)?;
+    output.set_permissions(metadata.permissions())?;
+    output.write_all(&MARK)?;
+    let mut encoder = zstd::Encoder::new(output, level)?;
+    encoder.include_contentsize(true)?;
+    encoder.set_pledged_src_size(Some(metadata.len()))?;
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    let output = encoder.finish()?;
+    if let Ok(modified) = metadata.modified() {
+        output.set_modified(modified)?;
+    }
+
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+// Decompresses every compressed file inside an item in place
+fn decompress(path: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for item in fs::read_dir(path)? {
+            decompress(&item?.path())?;
+        }
+    } else if is_compressed(path) {
+        let name = path.to_string_lossy().to_string();
+        decompress_file(path, Path::new(&name[..name.len() - COMPRESSED.len()]))?;
+    }
+    Ok(())
+}
+
+// Decompresses a file into a destination, removing the compressed file
+fn decompress_file(source: &Path, dest: &Path) -> Result<()> {
+    let metadata = fs::metadata(source)?;
+    let mut output = File::create(dest)?;
+    output.set_permissions(metadata.permissions())?;
+    zstd::stream::copy_decode(File::open(source)?, &mut output)?;
+    if let Ok(modified) = metadata.modified() {
+        output.set_modified(modified)?;
+    }
+
+    fs::remove_file(source)?;
+    Ok(())
+}
+
+// Reads how big a compressed file was from its header, which is at most 18 bytes long and comes after the mark
+fn content_size(path: &Path) -> Result<u64> {
+    let mut header = [0; MARK.len() + 18];
+    let read = File::open(path)?.read(&mut header)?;
+
+    match zstd::zstd_safe::get_frame_content_size(&header[MARK.len().min(read)..read]) {
+        Ok(Some(size)) => Ok(size),
+        _ => Ok(fs::metadata(path)?.len()),
+    }
+}
+
+/// Moves a file or directory to a path, copying it over if it's going into another filesystem
+pub fn move_item(source: &Path, dest: &Path, task: &str) -> Result<()> {
+    if fs::rename(source, dest).is_ok() {
+        return Ok(());
+    }
+
+    // Moving across filesystems copies everything, which can take a while
+    let mut progress = Progress::measure(task, source);
+    if source.is_dir() && !source.is_symlink() {
+        let options = CopyOptions {
+            copy_inside: true,
+            ..CopyOptions::new()
+        };
+        let mut file = String::new();
+        move_dir_with_progress(source, dest, &options, |process| {
+            if process.file_name != file {
+                file = process.file_name;
+                progress.add_file();
+            }
+            progress.set_bytes(process.copied_bytes);
+            TransitProcessResult::ContinueOrAbort
+        })?;
+    } else {
+        progress::copy(source, dest, &mut progress)?;
+        fs::remove_file(source)?;
+    }
+    progress.finish();
+    Ok(())
+}
+
+/// Creates an empty directory for a test to put files into, unique to it and the run it's in
+#[cfg(test)]
+pub(crate) fn scratch(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vento-test-{}-{}", std::process::id(), test));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
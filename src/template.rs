@@ -0,0 +1,177 @@
+/*
+ * Vento, a CLI inventory for your files.
+ * Copyright (C) 2023 Lux Aliaga
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::{
+    common,
+    message::{append_emoji, throw_error, EmojiType, ErrorType},
+    store,
+};
+use anyhow::Result;
+use chrono::Local;
+use colored::Colorize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The name of the templates slot inside the Vento directory, whose items are copied out when dropped instead of moved
+pub const TEMPLATES: &str = "templates";
+
+/// Whether a slot name refers to the templates slot
+pub fn is_templates(slot: &str) -> bool {
+    matches!(slot, "t" | "templates")
+}
+
+/// What placeholders in a template are replaced with when it's rendered
+pub struct Variables {
+    /// Replaces {{project}}, with the name of the git repository or directory a template is dropped into
+    pub project: String,
+    /// Replaces {{date}}, with today's date
+    pub date: String,
+    /// Replaces {{author}}, with the name set in git's config, if there's one
+    pub author: Option<String>,
+}
+
+impl Variables {
+    /// Finds the values of every variable for a template dropped into a directory
+    pub fn detect(dest: &Path) -> Variables {
+        let project = dest
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .unwrap_or(dest)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let author = Command::new("git")
+            .args(["config", "user.name"])
+            .current_dir(dest)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .filter(|author| !author.is_empty());
+
+        Variables {
+            project,
+            date: Local::now().format("%Y-%m-%d").to_string(),
+            author,
+        }
+    }
+
+    /// Replaces the placeholders in some text, leaving the ones without a value untouched
+    pub fn render(&self, text: &str) -> String {
+        let mut rendered = text
+            .replace("{{project}}", &self.project)
+            .replace("{{date}}", &self.date);
+        if let Some(author) = &self.author {
+            rendered = rendered.replace("{{author}}", author);
+        }
+        rendered
+    }
+}
+
+/// Copies an item out of the templates slot into a destination, rendering the placeholders in it if asked to
+pub fn copy(file: &str, dest: PathBuf, render: bool, message: bool) -> Result<()> {
+    if !common::env_config()?.vento_dir.is_dir() {
+        throw_error(ErrorType::NotInitialized)?;
+    }
+
+    // Templates inside directories in the slot can be copied out too, by their own name
+    store::check_path(Path::new(file))?;
+    let source = store::open_dir(TEMPLATES)?.join(file);
+    if file.is_empty() || fs::symlink_metadata(&source).is_err() {
+        throw_error(ErrorType::NoFileOrDir)?;
+    }
+    let filename = Path::new(file)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+
+    let dest = fs::canonicalize(&dest)?;
+    let variables = render.then(|| Variables::detect(&dest));
+    let name = match &variables {
+        Some(variables) => variables.render(&filename),
+        None => filename,
+    };
+    let destpath = dest.join(&name);
+    if fs::symlink_metadata(&destpath).is_ok() {
+        throw_error(ErrorType::ExistsDestination)?;
+    }
+
+    copy_item(&source, &destpath, variables.as_ref())?;
+
+    if message {
+        println!(
+            "{}{} {} {} {} {}{}",
+            append_emoji(EmojiType::Success)?,
+            "Copied".green(),
+            file.bold(),
+            "from".green(),
+            TEMPLATES.magenta().bold(),
+            "slot".green(),
+            match common::parse_config()?.display_dir {
+                true => format!("{} {}", " into".green(), destpath.display()),
+                false => String::new(),
+            }
+        );
+    }
+    Ok(())
+}
+
+// Copies a file or directory, rendering the names and contents of everything inside of it if there are variables
+fn copy_item(source: &Path, dest: &Path, variables: Option<&Variables>) -> Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+
+    if metadata.is_dir() {
+        fs::create_dir(dest)?;
+        for item in fs::read_dir(source)? {
+            let item = item?;
+            let name = item.file_name().to_string_lossy().to_string();
+            let name = match variables {
+                Some(variables) => variables.render(&name),
+                None => name,
+            };
+            copy_item(&item.path(), &dest.join(name), variables)?;
+        }
+        fs::set_permissions(dest, metadata.permissions())?;
+        return Ok(());
+    }
+
+    // Files which aren't text, such as images, are copied as they are
+    let contents = match (variables, metadata.is_file()) {
+        (Some(variables), true) => match fs::read_to_string(source) {
+            Ok(text) => Some(variables.render(&text)),
+            Err(_) => None,
+        },
+        _ => None,
+    };
+    match contents {
+        Some(contents) => {
+            fs::write(dest, contents)?;
+            fs::set_permissions(dest, metadata.permissions())?;
+        }
+        None => {
+            fs::copy(source, dest)?;
+        }
+    }
+    Ok(())
+}
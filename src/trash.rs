@@ -0,0 +1,433 @@
+/*
+ * Vento, a CLI inventory for your files.
+ * Copyright (C) 2023 Lux Aliaga
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::{
+    common::{self, Action, HistoryData},
+    dedup,
+    message::{append_emoji, throw_error, EmojiType, ErrorType},
+    meta, store,
+};
+use anyhow::{bail, Result};
+use chrono::Local;
+use colored::Colorize;
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The name of the trash inside the Vento directory, where items are kept for a while after being thrown away
+pub const TRASH: &str = ".trash";
+
+/// Where discarded items are thrown away into
+#[derive(Clone, Copy)]
+pub enum Backend {
+    /// The trash inside the Vento directory, which gc purges after a grace period
+    Internal,
+    /// The user's trash, following the freedesktop.org Trash specification
+    Xdg,
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(backend: &str) -> Result<Self> {
+        match backend {
+            "internal" => Ok(Backend::Internal),
+            "xdg" => Ok(Backend::Xdg),
+            _ => bail!(
+                "{}",
+                format!(
+                    "No such trash backend. Valid backends are {} and {}",
+                    "internal".bold(),
+                    "xdg".bold()
+                )
+                .red()
+            ),
+        }
+    }
+}
+
+/// Throws an item in a slot away into the trash, recording it in the history so it can be undone
+pub fn discard(file: &str, slot: &str, message: bool) -> Result<()> {
+    if !common::env_config()?.vento_dir.is_dir() {
+        throw_error(ErrorType::NotInitialized)?;
+    }
+    let slot = slot_name(slot)?;
+    // Items inside directories in the slot can be thrown away too, as long as they stay inside of it
+    store::check_path(Path::new(file))?;
+    if file.is_empty() || !store::open(slot)?.exists(Path::new(file))? {
+        bail!(
+            "{}",
+            format!("No item named {} in the {} slot", file.bold(), slot).red()
+        );
+    }
+
+    let backend: Backend = common::parse_config()?.trash_backend.parse()?;
+    let dest = match backend {
+        Backend::Internal => internal_dir()?.join(slot).join(file),
+        Backend::Xdg => xdg_reserve(&origin(slot, file)?)?,
+    };
+    settle(file, slot, &dest, true)?;
+
+    if message {
+        println!(
+            "{}{} {} {} {} {}",
+            append_emoji(EmojiType::Success)?,
+            "Discarded".green(),
+            file.bold(),
+            "from".green(),
+            match slot {
+                "active" => slot.green(),
+                _ => slot.blue(),
+            }
+            .bold(),
+            match (backend, common::parse_config()?.display_dir) {
+                (Backend::Xdg, true) => format!(
+                    "{} {}",
+                    "slot into".green(),
+                    dest.parent().unwrap_or(&dest).display()
+                ),
+                _ => "slot".green().to_string(),
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Moves every expired item into the trash, then purges whatever has been in the trash for longer than the grace period
+pub fn gc(message: bool) -> Result<()> {
+    if !common::env_config()?.vento_dir.is_dir() {
+        throw_error(ErrorType::NotInitialized)?;
+    }
+
+    // Every item thrown away in one go shares a directory, named after when it happened
+    let dir = internal_dir()?;
+    let mut trashed = 0;
+    for slot in ["active", "inactive"] {
+        for (file, metadata) in meta::slot(slot)? {
+            if !metadata.expired() {
+                continue;
+            }
+            trash(&file, slot, &dir.join(slot).join(&file), true)?;
+            trashed += 1;
+
+            if message {
+                println!(
+                    "{}{} {} {} {} {}",
+                    append_emoji(EmojiType::Success)?,
+                    "Trashed".green(),
+                    file.bold(),
+                    "from".green(),
+                    match slot {
+                        "active" => slot.green(),
+                        _ => slot.blue(),
+                    }
+                    .bold(),
+                    "slot".green()
+                );
+            }
+        }
+    }
+
+    let purged = purge(common::parse_config()?.gc_grace)?;
+    let collected = dedup::collect()?;
+
+    if message {
+        println!(
+            "{}{}",
+            append_emoji(EmojiType::Inventory)?,
+            match (trashed, purged, collected) {
+                (0, 0, 0) => String::from("No expired items, and nothing to purge from the trash"),
+                (_, _, 0) => format!(
+                    "Moved {} expired items into the trash, and purged {} from it",
+                    trashed, purged
+                ),
+                _ => format!(
+                    "Moved {} expired items into the trash, and purged {} from it. Removed {} objects which weren't used anymore",
+                    trashed, purged, collected
+                ),
+            }
+            .green()
+        );
+    }
+    Ok(())
+}
+
+/// Moves an item from a slot into the trash, where it's kept under the destination given
+pub fn trash(file: &str, slot: &str, dest: &Path, save_history: bool) -> Result<()> {
+    // Items thrown away into the user's trash need to be described for it to restore them, under a name nothing else has taken
+    if let Some(info) = xdg_info(dest) {
+        if fs::symlink_metadata(dest).is_ok() || fs::symlink_metadata(&info).is_ok() {
+            bail!(
+                "{}",
+                format!(
+                    "{} can't be thrown away into {}, since something else in the trash has taken its name",
+                    file.bold(),
+                    dest.display()
+                )
+                .red()
+            );
+        }
+        write_info(&info, &origin(slot, file)?)?;
+    }
+    settle(file, slot, dest, save_history)
+}
+
+// Moves an item into the trash under a name already claimed for it, letting go of the claim if the item never got there
+fn settle(file: &str, slot: &str, dest: &Path, save_history: bool) -> Result<()> {
+    let moved = move_item(file, slot, dest, save_history);
+    if moved.is_err() && fs::symlink_metadata(dest).is_err() {
+        if let Some(info) = xdg_info(dest) {
+            let _ = fs::remove_file(info);
+        }
+    }
+    moved
+}
+
+fn move_item(file: &str, slot: &str, dest: &Path, save_history: bool) -> Result<()> {
+    let store = store::open(slot)?;
+    if !store.exists(Path::new(file))? {
+        throw_error(ErrorType::NoFileOrDir)?;
+    }
+
+    fs::create_dir_all(dest.parent().unwrap_or(dest))?;
+    store.get(file, dest)?;
+    meta::dropped(slot, file, dest)?;
+
+    if save_history {
+        common::history(HistoryData {
+            id: 0,
+            path: Some(fs::canonicalize(dest)?),
+            file: Some(String::from(file)),
+            slot: Some(String::from(slot)),
+            action: Action::Trash,
+            current: 1,
+            time: 0,
+            link: false,
+        })?;
+    }
+    Ok(())
+}
+
+/// Brings an item back from the trash into its slot, where it won't expire anymore
+pub fn restore(file: &str, slot: &str, trashed: &Path) -> Result<()> {
+    if fs::symlink_metadata(trashed).is_err() {
+        bail!(
+            "{}",
+            format!(
+                "{} can't be restored, since it's no longer in the trash",
+                file.bold()
+            )
+            .red()
+        );
+    }
+    // Whatever is under the name in the user's trash by now may have been thrown away by someone else
+    if let Some(info) = xdg_info(trashed) {
+        let path = format!("Path={}", encode(&origin(slot, file)?.to_string_lossy()));
+        if !fs::read_to_string(info).is_ok_and(|info| info.lines().any(|line| line == path)) {
+            bail!(
+                "{}",
+                format!(
+                    "{} can't be restored, since {} in the trash is something else by now",
+                    file.bold(),
+                    trashed.display()
+                )
+                .red()
+            );
+        }
+    }
+    let store = store::open(slot)?;
+    if store.exists(Path::new(file))? {
+        throw_error(ErrorType::ExistsInventory)?;
+    }
+
+    store.put(trashed, file)?;
+    meta::taken(trashed, slot, file)?;
+    meta::expire(slot, file, None)?;
+
+    if let Some(info) = xdg_info(trashed) {
+        let _ = fs::remove_file(info);
+    }
+
+    // Leaves no empty directories behind in the internal trash
+    let trash = common::env_config()?.vento_dir.join(TRASH);
+    for dir in trashed.ancestors().skip(1) {
+        if dir == trash || !dir.starts_with(&trash) || fs::read_dir(dir)?.next().is_some() {
+            break;
+        }
+        fs::remove_dir(dir)?;
+    }
+    Ok(())
+}
+
+/// Permanently removes everything that has been in the internal trash for longer than some amount of seconds, returning how many items were removed
+pub fn purge(grace: u64) -> Result<usize> {
+    let trash = common::env_config()?.vento_dir.join(TRASH);
+    if !trash.is_dir() {
+        return Ok(0);
+    }
+
+    let mut purged = 0;
+    for dir in fs::read_dir(&trash)? {
+        let dir = dir?.path();
+        let time = dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<i64>().ok());
+
+        match time {
+            Some(time) if now() - time > grace as i64 => {
+                for slot in fs::read_dir(&dir)? {
+                    purged += fs::read_dir(slot?.path())?.count();
+                }
+                meta::forget_dropped(&fs::canonicalize(&dir)?)?;
+                fs::remove_dir_all(&dir)?;
+            }
+            _ => (),
+        }
+    }
+    Ok(purged)
+}
+
+// The directory inside the internal trash items thrown away right now go into
+fn internal_dir() -> Result<PathBuf> {
+    Ok(common::env_config()?
+        .vento_dir
+        .join(TRASH)
+        .join(now().to_string()))
+}
+
+// Picks a name in the user's trash nobody else has taken, claiming it by writing its info file first as the specification asks
+fn xdg_reserve(source: &Path) -> Result<PathBuf> {
+    let trash = match dirs::data_dir() {
+        Some(dir) => dir.join("Trash"),
+        None => bail!(
+            "{}",
+            "Couldn't find the trash. Set trash.backend to internal in the config file to use Vento's own".red()
+        ),
+    };
+    fs::create_dir_all(trash.join("files"))?;
+    fs::create_dir_all(trash.join("info"))?;
+
+    let name = source
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let mut count = 1;
+    loop {
+        let candidate = match count {
+            1 => name.clone(),
+            _ => format!("{}.{}", name, count),
+        };
+        let dest = trash.join("files").join(&candidate);
+        count += 1;
+
+        if fs::symlink_metadata(&dest).is_ok() {
+            continue;
+        }
+        match write_info(
+            &trash.join("info").join(format!("{}.trashinfo", candidate)),
+            source,
+        ) {
+            Ok(()) => return Ok(dest),
+            Err(error)
+                if error
+                    .downcast_ref::<std::io::Error>()
+                    .is_some_and(|error| error.kind() == ErrorKind::AlreadyExists) =>
+            {
+                continue
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+// Finds the info file describing an item inside the user's trash, if that's where it is
+fn xdg_info(trashed: &Path) -> Option<PathBuf> {
+    let files = trashed.parent()?;
+    let trash = files.parent()?;
+    if files.file_name()? != "files" || !trash.join("info").is_dir() {
+        return None;
+    }
+
+    let mut name = trashed.file_name()?.to_os_string();
+    name.push(".trashinfo");
+    Some(trash.join("info").join(name))
+}
+
+// Writes the info file for an item thrown away into the user's trash, failing if it already exists
+fn write_info(info: &Path, source: &Path) -> Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(info)?;
+    write!(
+        file,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode(&source.to_string_lossy()),
+        Local::now().format("%Y-%m-%dT%H:%M:%S")
+    )?;
+    Ok(())
+}
+
+// Percent-encodes a path the way info files store it
+fn encode(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// Where an item in a slot is, as far as the user's trash is concerned
+fn origin(slot: &str, file: &str) -> Result<PathBuf> {
+    Ok(match store::open(slot)?.path() {
+        Some(dir) => dir.join(file),
+        None => PathBuf::from(file),
+    })
+}
+
+fn slot_name(slot: &str) -> Result<&'static str> {
+    match slot {
+        "a" | "active" => Ok("active"),
+        "i" | "inactive" => Ok("inactive"),
+        _ => bail!(
+            "{}",
+            format!(
+                "No such slot. Valid slots are {} and {}",
+                "active".green().bold(),
+                "inactive".blue().bold()
+            )
+            .red()
+        ),
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}